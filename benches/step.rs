@@ -0,0 +1,44 @@
+// Steps random soups, the workload where the tiled engine spends its time
+// on calculation rather than bookkeeping.
+
+use conk::life::Universe;
+use conk::reference;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// The same soups every run.
+fn soup(size: i64) -> Universe {
+    let mut universe = Universe::default();
+    for (x, y) in reference::soup(0x2545_f491_4f6c_dd1d, -size / 2, -size / 2, size) {
+        universe.set_cell(x, y);
+    }
+    universe
+}
+
+fn step_soups(c: &mut Criterion) {
+    let mut group = c.benchmark_group("soup");
+    group.sample_size(20);
+    for &size in &[64, 256, 1024] {
+        // Start from a soup that has been running for a while, so most
+        // tiles are active but the initial burst of births is over.
+        let mut universe = soup(size);
+        for _ in 0..50 {
+            universe.step();
+        }
+        group.bench_with_input(BenchmarkId::new("10 generations", size), &universe, |b, universe| {
+            b.iter_batched(
+                || universe.clone(),
+                |mut universe| {
+                    for _ in 0..10 {
+                        universe.step();
+                    }
+                    universe
+                },
+                criterion::BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, step_soups);
+criterion_main!(benches);
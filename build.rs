@@ -0,0 +1,38 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Embeds every src/patterns/<rule>/*.rle file so the pattern catalogue is
+// built from the directory tree rather than a hand-maintained list.
+fn main() {
+    let root = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("src").join("patterns");
+    println!("cargo:rerun-if-changed={}", root.display());
+
+    let mut files: Vec<(String, String, PathBuf)> = Vec::new();
+    for folder in fs::read_dir(&root).unwrap() {
+        let folder = folder.unwrap().path();
+        if !folder.is_dir() {
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", folder.display());
+        let group = folder.file_name().unwrap().to_string_lossy().into_owned();
+
+        for file in fs::read_dir(&folder).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|extension| extension == "rle") {
+                let stem = file.file_stem().unwrap().to_string_lossy().into_owned();
+                files.push((group.clone(), stem, file));
+            }
+        }
+    }
+    files.sort();
+
+    let mut table = String::from("&[\n");
+    for (group, stem, path) in files {
+        table.push_str(&format!("    ({:?}, {:?}, include_str!({:?})),\n", group, stem, path.display().to_string()));
+    }
+    table.push_str("]\n");
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("patterns.rs");
+    fs::write(out, table).unwrap();
+}
@@ -1,11 +1,25 @@
+use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
-use web_sys::{HtmlCanvasElement, MouseEvent, TouchEvent, TouchList, WheelEvent, WebGlBuffer, WebGlShader, WebGlProgram,WebGlUniformLocation};
+use web_sys::{Blob, BlobPropertyBag, DragEvent, HtmlAnchorElement, HtmlCanvasElement, Url, KeyboardEvent, MouseEvent, TouchEvent, TouchList, WheelEvent, WebGlBuffer, WebGlShader, WebGlProgram, WebGlTexture, WebGlUniformLocation};
 use web_sys::WebGl2RenderingContext as GL;
-use yew::services::{ConsoleService, IntervalService, RenderService, Task};
-use yew::{html, Component, ComponentLink, Html, NodeRef, ShouldRender, components::Select};
-use yew::html::ChangeData;
-
+use yew::format::Json;
+use yew::services::{ReaderService, RenderService, Task, TimeoutService};
+use yew::services::reader::{File, FileData, ReaderTask};
+use yew::services::storage::{Area, StorageService};
+use yew::{html, Component, ComponentLink, Html, NodeRef, Properties, ShouldRender, components::Select};
+use yew::html::{ChangeData, InputData};
+
+use crate::catalogue::{Catalogue, Entry, RuleSet};
+use crate::export;
+use crate::life;
+use crate::scheduler::Scheduler;
+use crate::share::SharedView;
+use crate::worker::{self, Edit, Request, Response};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
 use std::time::Duration;
 
 extern crate js_sys;
@@ -14,92 +28,123 @@ const MOVE_THRESHOLD: i32 = 3;
 const DEFAULT_CELL_SIZE: f32 = 10.0;
 const DEFAULT_ZOOM: f32 = -0.02;
 const RANDOMIZE_FRACTION: f64 = 0.20;
-const DEFAULT_FRAMES_PER_SECOND: u64 = 60;
-
-#[derive(PartialEq, Debug, Clone)]
-pub enum Pattern {
+const DEFAULT_SPEED: u64 = 60;
+// Generations per second on offer; 0 runs as many as fit each frame.
+const SPEEDS: [u64; 13] = [1, 2, 5, 10, 30, 60, 120, 250, 500, 1000, 2500, 10000, 0];
+// Time for the generations run each frame, leaving the rest for drawing.
+const FRAME_BUDGET_MS: f64 = 10.0;
+const STATS_REFRESH_MS: f64 = 500.0;
+const MIDDLE_BUTTON: i16 = 1;
+const MAX_UNDO: usize = 100;
+const SESSION_KEY: &str = "conk.session";
+const SAVE_DELAY_MS: u64 = 1000;
+const SAVE_MAX_WAIT_MS: f64 = 5000.0;
+// The furthest out and in the view can be zoomed.
+const MIN_CELL_SIZE: f32 = 1.0 / (1u64 << 40) as f32;
+const MAX_CELL_SIZE: f32 = 1000.0;
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum Tool {
     ToggleCell,
-    Glider,
-    Pulsar,
-    Pentadecathlon,
-    LWSS,
-    MWSS,
-    HWSS,
-    GosperGliderGun,
-    RPentamino,
-    Diehard,
-    Acorn,
-    Sawtooth1212,
-    Homer,
-    DRHOscillators,
-    C3Ladder,
-    C4Ladder,
-    QuadraticGrowth,
-    P200Oscillator,
-    LFODMisc,
-    SeedsMisc,
-    SimpleReplicator,
+    Draw,
+    Erase,
+    Line,
+    Rectangle,
+    FilledRectangle,
+    Ellipse,
+    FilledEllipse,
+    Select,
+    Stamp,
+}
+
+impl fmt::Display for Tool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Tool::ToggleCell => "Toggle Cell",
+            Tool::Draw => "Draw",
+            Tool::Erase => "Erase",
+            Tool::Line => "Line",
+            Tool::Rectangle => "Rectangle",
+            Tool::FilledRectangle => "Filled Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::FilledEllipse => "Filled Ellipse",
+            Tool::Select => "Select Region",
+            Tool::Stamp => "Place Pattern",
+        };
+        write!(f, "{}", name)
+    }
 }
 
-impl ToString for Pattern {
-    fn to_string(&self) -> String {
+impl Tool {
+    // The cells a shape tool draws when dragged between two cells.
+    fn shape(&self, start: (i64, i64), end: (i64, i64)) -> Option<Vec<(i64, i64)>> {
         match self {
-            Pattern::ToggleCell => "Toggle Cell".to_string(),
-            Pattern::Glider => "Glider (Conway)".to_string(),
-            Pattern::Pulsar => "Pulsar (Conway)".to_string(),
-            Pattern::Pentadecathlon => "Pentadecathlon (Conway)".to_string(),
-            Pattern::LWSS => "Leightweight spaceship (Conway)".to_string(),
-            Pattern::MWSS => "Middleweight spaceship (Conway)".to_string(),
-            Pattern::HWSS => "Heavyweight spaceship (Conway)".to_string(),
-            Pattern::GosperGliderGun => "Gosper glider gun (Conway)".to_string(),
-            Pattern::RPentamino => "R-pentamino (Conway)".to_string(),
-            Pattern::Diehard => "Diehard (Conway)".to_string(),
-            Pattern::Acorn => "Acorn (Conway)".to_string(),
-            Pattern::Sawtooth1212 => "Sawtooth 1212 (Conway)".to_string(),
-            Pattern::Homer => "Homer (Conway)".to_string(),
-            Pattern::DRHOscillators => "Oscillator collection (Conway)".to_string(),
-            Pattern::C3Ladder => "c/3 ladder (Life Without Death)".to_string(),
-            Pattern::C4Ladder => "c/4 ladder (Life Without Death)".to_string(),
-            Pattern::QuadraticGrowth => "Quadratic growth (Life Without Death)".to_string(),
-            Pattern::P200Oscillator => "p200 oscillator (Day & Night)".to_string(),
-            Pattern::LFODMisc => "Miscellaneous (Live Free or Die)".to_string(),
-            Pattern::SeedsMisc => "Miscellaneous (Seeds)".to_string(),
-            Pattern::SimpleReplicator => "Simple Replicator (HighLife)".to_string(),
+            Tool::Line => Some(life::line(start, end)),
+            Tool::Rectangle => Some(life::rectangle(start, end, false)),
+            Tool::FilledRectangle => Some(life::rectangle(start, end, true)),
+            Tool::Ellipse => Some(life::ellipse(start, end, false)),
+            Tool::FilledEllipse => Some(life::ellipse(start, end, true)),
+            _ => None,
+        }
+    }
+}
+
+// Which point of a pattern lands on the clicked cell.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Anchor {
+    TopLeft,
+    Center,
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anchor::TopLeft => write!(f, "Place by top-left corner"),
+            Anchor::Center => write!(f, "Place by center"),
+        }
+    }
+}
+
+// Generations per second to aim for, 0 for as fast as possible.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Speed(u64);
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            0 => write!(f, "Speed: as fast as possible"),
+            speed => write!(f, "Speed: {} generations/s", speed),
         }
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
-pub enum RuleSet {
-    LifeWithoutDeath,
-    Conway,
-    DayAndNight,
-    LiveFreeOrDie,
-    Replicator,
-    Replicator2,
-    Seeds,
-    Maze,
-    EightLife,
-    LongLife,
-    HighLife,
-    DryLife,
+// Side length of the square painted by the draw and erase tools.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BrushSize(i64);
+
+impl fmt::Display for BrushSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Brush {}x{}", self.0, self.0)
+    }
 }
 
-impl ToString for RuleSet {
-    fn to_string(&self) -> String {
+// Pixels per cell in exported images.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ExportScale(u32);
+
+impl fmt::Display for ExportScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Export {}px per cell", self.0)
+    }
+}
+
+impl fmt::Display for life::PasteMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            RuleSet::Conway => "Conway - B3/S23".to_string(),
-            RuleSet::LifeWithoutDeath => "Life Without Death - B3/S012345678".to_string(),
-            RuleSet::DayAndNight => "Day & Night - B3678/S34678".to_string(),
-            RuleSet::LiveFreeOrDie => "Live Free or Die - B2/S0".to_string(),
-            RuleSet::Replicator => "Replicator - B1357/S1357".to_string(),
-            RuleSet::Replicator2 => "Replicator 2 - B1357/S02468".to_string(),
-            RuleSet::Seeds => "Seeds - B2/S".to_string(),
-            RuleSet::Maze => "Maze - B3/S12345".to_string(),
-            RuleSet::EightLife => "EightLife - B3/S238".to_string(),
-            RuleSet::LongLife => "Long Life - B345/S5".to_string(),
-            RuleSet::HighLife => "HighLife - B36/S23".to_string(),
-            RuleSet::DryLife => "DryLife - B37/S23".to_string(),
+            life::PasteMode::Copy => write!(f, "Paste: copy"),
+            life::PasteMode::Or => write!(f, "Paste: or"),
+            life::PasteMode::And => write!(f, "Paste: and"),
+            life::PasteMode::Xor => write!(f, "Paste: xor"),
         }
     }
 }
@@ -108,31 +153,132 @@ pub enum Msg {
     RenderGl,
     Step,
     PlayOrPause,
-    StepIfNotPaused,
     Zoom(WheelEvent),
     ToggleOrStartMove(MouseEvent),
     MaybeMove(MouseEvent),
     ToggleOrEndMove(MouseEvent),
     Randomize,
     Clear,
-    SetPattern(Pattern),
+    SetTool(Tool),
+    SetEntry(ChangeData),
+    Search(String),
     SetRuleSet(RuleSet),
-    ChangeSpeed(ChangeData),
+    SetAnchor(Anchor),
+    SetBrushSize(BrushSize),
+    SetPasteMode(life::PasteMode),
+    LeaveCanvas,
+    SetSpeed(Speed),
     ZoomOrMove(TouchEvent),
     StartZoomOrMove(TouchEvent),
     EndZoomOrMove(TouchEvent),
+    KeyDown(KeyboardEvent),
+    PickFile(ChangeData),
+    DragOver(DragEvent),
+    DropFile(DragEvent),
+    FileLoaded(FileData, Option<(i64, i64)>),
+    PasteText(Option<String>),
+    SaveSession,
+    LeavePage,
+    ResetSession,
+    Share,
+    SaveSnapshot,
+    SetExportScale(ExportScale),
+    ToggleExportGrid,
+    ExportPng,
+    SetGifGenerations(ChangeData),
+    SetGifPerFrame(ChangeData),
+    SetGifDelay(ChangeData),
+    ExportGif,
+    Engine(Response),
+}
+
+// A link to a shared view, already parsed by run_app; an error if the link
+// could not be read.
+#[derive(Clone, Properties)]
+pub struct Props {
+    pub shared: Option<Result<SharedView, String>>,
+}
+
+// A rectangle of cells, in universe coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selection {
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+}
+
+impl Selection {
+    fn from_corners(start: (i64, i64), end: (i64, i64)) -> Selection {
+        Selection {
+            x: start.0.min(end.0),
+            y: start.1.min(end.1),
+            width: (start.0 - end.0).abs() + 1,
+            height: (start.1 - end.1).abs() + 1,
+        }
+    }
+}
+
+type PromiseCallback = Closure<dyn FnMut(JsValue)>;
+
+// What is kept in localStorage so a reload picks up where the last visit
+// left off. The live cells are stored as RLE with the position of their
+// top-left corner.
+#[derive(Serialize, Deserialize)]
+struct Session {
+    cells: String,
+    cells_x: i64,
+    cells_y: i64,
+    generation: u64,
+    ruleset: RuleSet,
+    x: f32,
+    y: f32,
+    cell_size: f32,
+    speed: u64,
+    tool: Tool,
+    stamp: Option<String>,
+    entry: Option<(String, String)>,
 }
+
+// The cells in a region before an edit changed them.
+struct Snapshot {
+    x: i64,
+    y: i64,
+    pattern: life::Pattern,
+}
+
+// A program that draws live cells from a texture over the canvas: the
+// visible tiles (tiles.frag), or their density when zoomed out (density.frag).
+// `scale` is the size in pixels of what a texel holds.
+struct TextureProgram {
+    program: WebGlProgram,
+    position_attribute_location: u32,
+    resolution: Option<WebGlUniformLocation>,
+    origin: Option<WebGlUniformLocation>,
+    scale: Option<WebGlUniformLocation>,
+    height: Option<WebGlUniformLocation>,
+    color: Option<WebGlUniformLocation>,
+    texture: Option<WebGlTexture>,
+    max_texture_size: i64,
+}
+
 pub struct App {
     canvas: Option<HtmlCanvasElement>,
     gl: Option<GL>,
     node_ref: NodeRef,
     render_loop: Option<Box<dyn Task>>,
     link: ComponentLink<Self>,
-    #[allow(dead_code)]
-    timer: Box<dyn Task>,
+    speed: u64,
+    scheduler: Scheduler,
+    stats_shown_ms: f64,
+    storage: Option<StorageService>,
+    save_task: Option<Box<dyn Task>>,
+    // When the first change since the last save was made.
+    unsaved_since: Option<f64>,
     universe: life::Universe,
-    vertices: js_sys::Float32Array,
     program: Option<WebGlProgram>,
+    tiles: Option<TextureProgram>,
+    density: Option<TextureProgram>,
     position_attribute_location: Option<u32>,
     position_buffer: Option<WebGlBuffer>,
     resolution_uniform_location: Option<WebGlUniformLocation>,
@@ -143,29 +289,69 @@ pub struct App {
     cell_size: f32,
     move_start: Option<(i32, i32)>,
     is_moving: bool,
-    pattern: Pattern,
+    tool: Tool,
+    stamp: Option<life::Pattern>,
+    catalogue: Catalogue,
+    entry: Option<usize>,
+    search: String,
+    anchor: Anchor,
+    paste_mode: life::PasteMode,
+    cursor: Option<(i64, i64)>,
+    brush_size: BrushSize,
+    last_painted: Option<(i64, i64)>,
+    shape_start: Option<(i64, i64)>,
+    undo_stack: Vec<Vec<Snapshot>>,
+    reader_task: Option<ReaderTask>,
+    message: Option<String>,
+    clipboard_callbacks: Option<(PromiseCallback, PromiseCallback)>,
+    selection: Option<Selection>,
+    selection_start: Option<(i64, i64)>,
+    export_scale: ExportScale,
+    export_grid: bool,
+    animation: export::Animation,
     ruleset: RuleSet,
     touches: Option<TouchList>,
+    // The worker running the engine, and the latest frame from it. Without
+    // one, the page steps and draws its own universe.
+    engine: Option<worker::Bridge>,
+    frame: Option<worker::Frame>,
+    view_sent: Option<(i64, i64, i64, i64, i64)>,
+    // Edits applied here that the worker has yet to be sent.
+    edits: Vec<Edit>,
+    // When the worker started the generations it is running. No more are
+    // sent until they end.
+    run_started: Option<f64>,
+    // Whether the page's universe has fallen behind the worker's.
+    mirror: worker::Mirror,
+    // Messages that need the page's universe to be current, and the input
+    // that came after them.
+    deferred: Vec<Msg>,
+    _on_pagehide: Option<Closure<dyn FnMut()>>,
 }
 
 impl Component for App {
     type Message = Msg;
-    type Properties = ();
+    type Properties = Props;
 
-    fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
-        let mut interval = IntervalService::new();
-        let handle = interval.spawn(Duration::from_millis(1000 / DEFAULT_FRAMES_PER_SECOND), link.callback(|_| Msg::StepIfNotPaused));
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let engine = worker::Bridge::new(link.callback(Msg::Engine));
 
-        App {
+        let mut app = App {
             canvas: None,
             gl: None,
             link,
             node_ref: NodeRef::default(),
             render_loop: None,
-            timer: Box::new(handle),
+            speed: DEFAULT_SPEED,
+            scheduler: Scheduler::new(Some(DEFAULT_SPEED as f64), FRAME_BUDGET_MS),
+            stats_shown_ms: 0.0,
+            storage: StorageService::new(Area::Local).ok(),
+            save_task: None,
+            unsaved_since: None,
             universe: life::Universe::new(vec!(3), vec!(2, 3)),
-            vertices: js_sys::Float32Array::new_with_length(0),
             program: None,
+            tiles: None,
+            density: None,
             position_attribute_location: None,
             position_buffer: None,
             resolution_uniform_location: None,
@@ -176,36 +362,141 @@ impl Component for App {
             cell_size: DEFAULT_CELL_SIZE,
             move_start: None,
             is_moving: false,
-            pattern: Pattern::ToggleCell,
+            tool: Tool::ToggleCell,
+            stamp: None,
+            catalogue: Catalogue::builtin(),
+            entry: None,
+            search: String::new(),
+            anchor: Anchor::TopLeft,
+            paste_mode: life::PasteMode::Copy,
+            cursor: None,
+            brush_size: BrushSize(1),
+            last_painted: None,
+            shape_start: None,
+            undo_stack: Vec::new(),
+            reader_task: None,
+            message: None,
+            clipboard_callbacks: None,
+            selection: None,
+            selection_start: None,
+            export_scale: ExportScale(4),
+            export_grid: false,
+            animation: export::Animation { generations: 100, per_frame: 1, delay_ms: 100 },
             ruleset: RuleSet::Conway,
             touches: None,
+            engine,
+            frame: None,
+            view_sent: None,
+            edits: Vec::new(),
+            run_started: None,
+            mirror: worker::Mirror::default(),
+            deferred: Vec::new(),
+            _on_pagehide: None,
+        };
+        app.restore_session();
+        match props.shared {
+            Some(Ok(shared)) => app.open_shared(shared),
+            Some(Err(error)) => app.message = Some(format!("Could not open the shared link: {}", error)),
+            None => (),
+        }
+        app.reload_engine();
+
+        // Whatever is still unsaved is saved as the page is left.
+        if let Some(window) = web_sys::window() {
+            let leave = app.link.callback(|_| Msg::LeavePage);
+            let on_pagehide = Closure::wrap(Box::new(move || leave.emit(())) as Box<dyn FnMut()>);
+            window.set_onpagehide(Some(on_pagehide.as_ref().unchecked_ref()));
+            app._on_pagehide = Some(on_pagehide);
         }
+        app
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        let msg = match msg {
+            msg if self.must_wait(&msg) => {
+                self.prevent_default(&msg);
+                self.deferred.push(msg);
+                self.request_sync();
+                return false;
+            },
+            msg => msg,
+        };
+
+        match msg {
+            Msg::RenderGl | Msg::SaveSession | Msg::LeavePage | Msg::ResetSession | Msg::Share | Msg::SaveSnapshot => (),
+            Msg::SetExportScale(_) | Msg::ToggleExportGrid | Msg::ExportPng => (),
+            Msg::SetGifGenerations(_) | Msg::SetGifPerFrame(_) | Msg::SetGifDelay(_) | Msg::ExportGif => (),
+            Msg::Engine(_) => (),
+            _ => self.schedule_save(),
+        }
+
         match msg {
             Msg::RenderGl => {
                 // Render functions are likely to get quite large, so it is good practice to split
                 // it into it's own function rather than keeping it inline in the update match
                 // case. This also allows for updating other UI elements that may be rendered in
                 // the DOM like a framerate counter, or other overlaid textual elements.
+                self.run_scheduled();
                 self.render_gl();
-                false
+
+                // The stats change every frame while playing, but the page is
+                // only drawn again a few times a second to show them.
+                let now = now_ms();
+                if !self.paused && now - self.stats_shown_ms >= STATS_REFRESH_MS {
+                    self.stats_shown_ms = now;
+                    true
+                } else {
+                    false
+                }
             },
             Msg::Step => {
-                self.universe.step();
+                if self.engine.is_none() {
+                    self.universe.step();
+                } else {
+                    // Only the worker steps; the page catches up from its
+                    // snapshot, as it does after playing.
+                    self.send(Request::Run(1));
+                    self.mirror.ran();
+                    if self.paused {
+                        self.request_sync();
+                    }
+                }
                 false
             },
             Msg::PlayOrPause => {
                 self.paused = !self.paused;
+                if self.paused {
+                    self.scheduler.pause();
+                    self.request_sync();
+                }
                 true
             },
-            Msg::StepIfNotPaused => {
-                if !self.paused {
-                    self.universe.step();
+            Msg::Engine(Response::Frame(frame)) => {
+                if let (true, Some(started)) = (frame.generations > 0, self.run_started) {
+                    let now = now_ms();
+                    self.scheduler.ran(now, frame.generations, now - started);
+                    self.run_started = None;
                 }
+                self.frame = Some(frame);
                 false
             },
+            Msg::Engine(Response::Snapshot(snapshot)) => {
+                self.mirror.synced(&mut self.universe, &snapshot);
+
+                // No generations are run while syncing, so the page is current
+                // and the deferred messages act on the worker's cells.
+                let mut render = false;
+                for msg in std::mem::take(&mut self.deferred) {
+                    render |= self.update(msg);
+                }
+                if self.paused {
+                    self.request_sync();
+                }
+                if !self.mirror.is_stale() && self.unsaved_since.is_some() {
+                    self.schedule_save();
+                }
+                render
+            },
             Msg::Zoom(event) => {
                 let canvas = self.canvas.as_ref().unwrap();
                 let width = canvas.client_width() as f32;
@@ -214,7 +505,8 @@ impl Component for App {
                 let midpoint_x = (self.x + width / 2.0) / self.cell_size;
                 let midpoint_y = (self.y + height / 2.0) / self.cell_size;
 
-                self.cell_size += event.delta_y().min(5.0).max(-5.0) as f32 * DEFAULT_ZOOM * self.cell_size;
+                self.cell_size += event.delta_y().clamp(-5.0, 5.0) as f32 * DEFAULT_ZOOM * self.cell_size;
+                self.cell_size = self.cell_size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
 
                 self.x = midpoint_x * self.cell_size - width / 2.0;
                 self.y = midpoint_y * self.cell_size - height / 2.0;
@@ -222,11 +514,44 @@ impl Component for App {
             },
             Msg::ToggleOrStartMove(mouse_event) => {
                 self.move_start = Some((mouse_event.client_x(), mouse_event.client_y()));
+
+                // The middle button or shift pans with any tool.
+                if mouse_event.button() == MIDDLE_BUTTON || mouse_event.shift_key() {
+                    mouse_event.prevent_default();
+                    self.is_moving = true;
+                    return false;
+                }
+
+                let cell = self.cell_at(&mouse_event);
+                match self.tool {
+                    Tool::Select => self.selection_start = Some(cell),
+                    Tool::Draw | Tool::Erase => {
+                        self.begin_edit();
+                        self.paint(cell, cell);
+                        self.last_painted = Some(cell);
+                    },
+                    Tool::Line | Tool::Rectangle | Tool::FilledRectangle |
+                    Tool::Ellipse | Tool::FilledEllipse => self.shape_start = Some(cell),
+                    _ => (),
+                }
                 false
             },
             Msg::MaybeMove(mouse_event) => {
-
-                if let Some((start_x, start_y)) = self.move_start {
+                self.cursor = Some(self.cell_at(&mouse_event));
+
+                if let Some(last) = self.last_painted {
+                    let cell = self.cell_at(&mouse_event);
+                    self.paint(last, cell);
+                    self.last_painted = Some(cell);
+                } else if self.shape_start.is_some() {
+                    self.is_moving = true;
+                } else if let Some(start) = self.selection_start {
+                    let end = self.cell_at(&mouse_event);
+                    if start != end {
+                        self.selection = Some(Selection::from_corners(start, end));
+                        self.is_moving = true;
+                    }
+                } else if let Some((start_x, start_y)) = self.move_start {
                     if (start_x - mouse_event.client_x()).abs() > MOVE_THRESHOLD || (start_y - mouse_event.client_y()).abs() > MOVE_THRESHOLD {
                         self.is_moving = true;
                     }
@@ -241,97 +566,106 @@ impl Component for App {
             },
             Msg::ToggleOrEndMove(mouse_event) => {
 
-                if !self.is_moving {
-                    let canvas_rect = self.canvas.as_ref().unwrap().get_bounding_client_rect();
-                    let mut x = (((mouse_event.client_x() as f32 + self.x) as f32 - canvas_rect.left() as f32) / self.cell_size) as i64;
-                    let mut y = (((mouse_event.client_y() as f32 + self.y) as f32 - canvas_rect.top() as f32) / self.cell_size) as i64;
-
-                    if x < 0 {
-                        x -= 1;
+                if let Some(start) = self.shape_start {
+                    let end = self.cell_at(&mouse_event);
+                    if let Some(cells) = self.tool.shape(start, end) {
+                        self.begin_edit();
+                        let (x, y) = (start.0.min(end.0), start.1.min(end.1));
+                        self.snapshot(x, y, (start.0 - end.0).abs() + 1, (start.1 - end.1).abs() + 1);
+                        for (x, y) in cells {
+                            self.edit(Edit::SetCell(x, y));
+                        }
                     }
-                    if y < 0 {
-                        y -= 1;
+                } else if !self.is_moving {
+                    let (x, y) = self.cell_at(&mouse_event);
+
+                    match self.tool {
+                        Tool::ToggleCell => {
+                            self.begin_edit();
+                            self.snapshot(x, y, 1, 1);
+                            self.edit(Edit::ToggleCell(x, y));
+                        },
+                        Tool::Select => self.selection = None,
+                        Tool::Stamp => if let Some(stamp) = self.stamp.take() {
+                            let (x, y) = self.stamp_origin(x, y, &stamp);
+                            self.begin_edit();
+                            self.snapshot(x, y, stamp.width, stamp.height);
+                            self.edit(Edit::PastePattern(x, y, stamp.clone(), self.paste_mode));
+                            self.stamp = Some(stamp);
+                        },
+                        _ => (),
                     }
-
-                    match self.pattern {
-                        Pattern::ToggleCell => self.universe.toggle_cell(x, y),
-                        Pattern::Glider => self.universe.set_rle(x, y, include_str!("patterns/conway/glider.rle")),
-                        Pattern::Pulsar => self.universe.set_rle(x, y, include_str!("patterns/conway/pulsar.rle")),
-                        Pattern::Pentadecathlon => self.universe.set_rle(x, y, include_str!("patterns/conway/pentadecathlon.rle")),
-                        Pattern::LWSS => self.universe.set_rle(x, y, include_str!("patterns/conway/lwss.rle")),
-                        Pattern::MWSS => self.universe.set_rle(x, y, include_str!("patterns/conway/mwss.rle")),
-                        Pattern::HWSS => self.universe.set_rle(x, y, include_str!("patterns/conway/hwss.rle")),
-                        Pattern::GosperGliderGun => self.universe.set_rle(x, y, include_str!("patterns/conway/gosper_glider_gun.rle")),
-                        Pattern::RPentamino => self.universe.set_rle(x, y, include_str!("patterns/conway/r_pentamino.rle")),
-                        Pattern::Diehard => self.universe.set_rle(x, y, include_str!("patterns/conway/diehard.rle")),
-                        Pattern::Acorn => self.universe.set_rle(x, y, include_str!("patterns/conway/acorn.rle")),
-                        Pattern::Sawtooth1212 => self.universe.set_rle(x, y, include_str!("patterns/conway/sawtooth_1212.rle")),
-                        Pattern::Homer => self.universe.set_rle(x, y, include_str!("patterns/conway/homer.rle")),
-                        Pattern::DRHOscillators => self.universe.set_rle(x, y, include_str!("patterns/conway/DRH_oscillators.rle")),
-                        Pattern::C3Ladder => self.universe.set_rle(x, y, include_str!("patterns/life_without_death/c3_ladder.rle")),
-                        Pattern::C4Ladder => self.universe.set_rle(x, y, include_str!("patterns/life_without_death/c4_ladder.rle")),
-                        Pattern::QuadraticGrowth => self.universe.set_rle(x, y, include_str!("patterns/life_without_death/quadratic_growth.rle")),
-                        Pattern::P200Oscillator => self.universe.set_rle(x, y, include_str!("patterns/day_and_night/p200_oscillator.rle")),
-                        Pattern::LFODMisc => self.universe.set_rle(x, y, include_str!("patterns/live_free_or_die/misc.rle")),
-                        Pattern::SeedsMisc => self.universe.set_rle(x, y, include_str!("patterns/seeds/misc.rle")),
-                        Pattern::SimpleReplicator => self.universe.set_rle(x, y, include_str!("patterns/highlife/simple_replicator.rle")),
-                    } 
                 }
 
                 self.is_moving = false;
                 self.move_start = None;
+                self.selection_start = None;
+                self.last_painted = None;
+                self.shape_start = None;
                 false
             },
             Msg::Randomize => {
 
-                for x in (self.x as f32 / self.cell_size) as i32..=((self.x as f32 + self.canvas.as_ref().unwrap().width() as f32) / self.cell_size) as i32 {
-                    for y in (self.y as f32 / self.cell_size) as i32..=((self.y as f32 + self.canvas.as_ref().unwrap().height() as f32) / self.cell_size) as i32 {
+                for x in (self.x / self.cell_size) as i32..=((self.x + self.canvas.as_ref().unwrap().width() as f32) / self.cell_size) as i32 {
+                    for y in (self.y / self.cell_size) as i32..=((self.y + self.canvas.as_ref().unwrap().height() as f32) / self.cell_size) as i32 {
                         if js_sys::Math::random() < RANDOMIZE_FRACTION {
-                            self.universe.set_cell(x as i64, y as i64);
+                            self.edit(Edit::SetCell(x as i64, y as i64));
                         } else {
-                            self.universe.kill_cell(x as i64, y as i64);
+                            self.edit(Edit::KillCell(x as i64, y as i64));
                         }
                     }
                 }
                 false
             },
             Msg::Clear => {
-                self.universe.clear();
+                self.edit(Edit::Clear);
                 false
             },
-            Msg::SetPattern(pattern) => {
-                self.pattern = pattern;
+            Msg::SetTool(tool) => {
+                if tool != Tool::Select {
+                    self.selection = None;
+                }
+                self.tool = tool;
                 true
             },
-            Msg::SetRuleSet(rules) => {
-                match rules {
-                    RuleSet::Conway => self.universe.set_rules(vec!(3), vec!(2, 3)),
-                    RuleSet::LifeWithoutDeath => self.universe.set_rules(vec!(3), vec!(0, 1, 2, 3, 4, 5, 6, 7, 8)),
-                    RuleSet::DayAndNight => self.universe.set_rules(vec!(3,6,7,8), vec!(3,4,6,7,8)),
-                    RuleSet::LiveFreeOrDie => self.universe.set_rules(vec!(2), vec!(0)),
-                    RuleSet::Replicator => self.universe.set_rules(vec!(1,3,5,7), vec!(1,3,5,7)),
-                    RuleSet::Replicator2 => self.universe.set_rules(vec!(1,3,5,7), vec!(0,2,4,6,8)),
-                    RuleSet::Seeds => self.universe.set_rules(vec!(2), vec!()),
-                    RuleSet::Maze => self.universe.set_rules(vec!(3), vec!(1,2,3,4,5)),
-                    RuleSet::EightLife => self.universe.set_rules(vec!(3), vec!(2,3,8)),
-                    RuleSet::LongLife => self.universe.set_rules(vec!(3,4,5), vec!(5)),
-                    RuleSet::HighLife => self.universe.set_rules(vec!(3, 6), vec!(2,3)),           
-                    RuleSet::DryLife => self.universe.set_rules(vec!(3,7), vec!(2,3))   
-
+            Msg::SetEntry(ChangeData::Select(select)) => {
+                let entry = select.value().parse::<usize>().ok()
+                    .and_then(|index| self.catalogue.get(index).map(|entry| (index, entry)));
+                if let Some((index, entry)) = entry {
+                    self.stamp = Some(life::Pattern::from_rle(entry.rle).expect("catalogue patterns are valid RLE"));
+                    self.entry = Some(index);
+                    self.tool = Tool::Stamp;
+                    self.selection = None;
                 }
-                self.ruleset = rules;
                 true
             },
-            Msg::ChangeSpeed(speed) => {
-                let mut interval = IntervalService::new();
-                match speed {
-                    ChangeData::Value(s) => {
-                        let handle = interval.spawn(Duration::from_millis(1000 / s.parse::<u64>().unwrap()), self.link.callback(|_| Msg::StepIfNotPaused));
-                        self.timer = Box::new(handle);
-                    }
-                    _ => panic!("unexpected speed"),
-                }
-
+            Msg::SetEntry(_) => false,
+            Msg::Search(query) => {
+                self.search = query;
+                true
+            },
+            Msg::SetRuleSet(rules) => {
+                self.set_ruleset(rules);
+                true
+            },
+            Msg::SetAnchor(anchor) => {
+                self.anchor = anchor;
+                false
+            },
+            Msg::SetPasteMode(paste_mode) => {
+                self.paste_mode = paste_mode;
+                false
+            },
+            Msg::SetBrushSize(brush_size) => {
+                self.brush_size = brush_size;
+                false
+            },
+            Msg::LeaveCanvas => {
+                self.cursor = None;
+                false
+            },
+            Msg::SetSpeed(Speed(speed)) => {
+                self.set_speed(speed);
                 false
             },
             Msg::ZoomOrMove(event) => {
@@ -364,7 +698,8 @@ impl Component for App {
                     let midpoint_x = (self.x + width / 2.0) / self.cell_size;
                     let midpoint_y = (self.y + height / 2.0) / self.cell_size;
     
-                    self.cell_size += delta_y.min(5.0).max(-5.0) as f32 * DEFAULT_ZOOM * self.cell_size;
+                    self.cell_size += delta_y.clamp(-5.0, 5.0) * DEFAULT_ZOOM * self.cell_size;
+                    self.cell_size = self.cell_size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
     
                     self.x = midpoint_x * self.cell_size - width / 2.0;
                     self.y = midpoint_y * self.cell_size - height / 2.0;
@@ -380,6 +715,186 @@ impl Component for App {
                 self.touches = None;
                 false
             },
+            Msg::PickFile(ChangeData::Files(files)) => {
+                if let Some(file) = files.get(0) {
+                    self.read_file(file, None);
+                }
+                false
+            },
+            Msg::PickFile(_) => false,
+            Msg::DragOver(event) => {
+                // Required for the canvas to accept drops.
+                event.prevent_default();
+                false
+            },
+            Msg::DropFile(event) => {
+                event.prevent_default();
+                let file = event.data_transfer().and_then(|data| data.files()).and_then(|files| files.get(0));
+                if let Some(file) = file {
+                    let cell = self.cell_at(&event);
+                    self.read_file(file, Some(cell));
+                }
+                false
+            },
+            Msg::FileLoaded(file, _) if file.name.to_lowercase().ends_with(".conk") => {
+                self.reader_task = None;
+                match life::Universe::load(&file.content) {
+                    Ok(universe) => self.open_snapshot(universe),
+                    Err(error) => self.message = Some(format!("Could not load {}: {}", file.name, error)),
+                }
+                true
+            },
+            Msg::FileLoaded(file, drop_cell) => {
+                self.reader_task = None;
+                let name = file.name;
+                let parsed = String::from_utf8(file.content)
+                    .map_err(|_| life::ParseError("not a text file".to_string()))
+                    .and_then(|contents| life::Pattern::from_file(&name, &contents));
+
+                match parsed {
+                    Ok(pattern) => {
+                        if let Some((x, y)) = drop_cell {
+                            let (x, y) = self.stamp_origin(x, y, &pattern);
+                            self.begin_edit();
+                            self.snapshot(x, y, pattern.width, pattern.height);
+                            self.edit(Edit::PastePattern(x, y, pattern.clone(), self.paste_mode));
+                        }
+                        self.hold_pattern(pattern);
+                    },
+                    Err(error) => self.message = Some(format!("Could not load {}: {}", name, error)),
+                }
+                true
+            },
+            Msg::PasteText(text) => {
+                let parsed = text.ok_or_else(|| life::ParseError("the clipboard has no text".to_string()))
+                    .and_then(|text| life::Pattern::parse(&text));
+
+                match parsed {
+                    Ok(pattern) => self.hold_pattern(pattern),
+                    Err(error) => self.message = Some(format!("Could not paste: {}", error)),
+                }
+                true
+            },
+            Msg::SaveSession => {
+                self.save_task = None;
+                // The page's cells are behind the worker's while it runs.
+                // They are saved once synced on pause, or as the page is left.
+                if !self.mirror.is_stale() {
+                    self.unsaved_since = None;
+                    self.save_session();
+                }
+                false
+            },
+            Msg::LeavePage => {
+                if self.unsaved_since.is_some() {
+                    self.save_session();
+                }
+                false
+            },
+            Msg::ResetSession => {
+                self.reset_session();
+                true
+            },
+            Msg::Share => {
+                self.share();
+                true
+            },
+            Msg::SetExportScale(scale) => {
+                self.export_scale = scale;
+                false
+            },
+            Msg::ToggleExportGrid => {
+                self.export_grid = !self.export_grid;
+                true
+            },
+            Msg::ExportPng => {
+                let view = self.export_view();
+                let png = export::rasterise(&self.universe.live_cells(), &view).map(|image| image.to_png());
+                let saved = png.and_then(|png| self.download("conk.png", &png, "image/png").map_err(|_| "the download failed".to_string()));
+                if let Err(error) = saved {
+                    self.message = Some(format!("Could not export the image: {}", error));
+                }
+                true
+            },
+            Msg::SetGifGenerations(ChangeData::Value(value)) => {
+                self.animation.generations = value.parse().unwrap_or(self.animation.generations);
+                true
+            },
+            Msg::SetGifPerFrame(ChangeData::Value(value)) => {
+                self.animation.per_frame = value.parse().unwrap_or(self.animation.per_frame);
+                true
+            },
+            Msg::SetGifDelay(ChangeData::Value(value)) => {
+                self.animation.delay_ms = value.parse().unwrap_or(self.animation.delay_ms);
+                true
+            },
+            Msg::SetGifGenerations(_) | Msg::SetGifPerFrame(_) | Msg::SetGifDelay(_) => false,
+            Msg::ExportGif => {
+                // The selection, if there is one, or else the screen.
+                let mut view = self.export_view();
+                if let Some(selection) = self.selection {
+                    view.x = selection.x;
+                    view.y = selection.y;
+                    view.width = selection.width as u32;
+                    view.height = selection.height as u32;
+                }
+                let gif = export::animate(&self.universe, &view, &self.animation);
+                let saved = gif.and_then(|gif| self.download("conk.gif", &gif, "image/gif").map_err(|_| "the download failed".to_string()));
+                if let Err(error) = saved {
+                    self.message = Some(format!("Could not export the animation: {}", error));
+                }
+                true
+            },
+            Msg::SaveSnapshot => {
+                let name = format!("generation-{}.conk", self.universe.generation);
+                if self.download(&name, &self.universe.save(), "application/octet-stream").is_err() {
+                    self.message = Some("Could not save the snapshot.".to_string());
+                }
+                true
+            },
+            Msg::KeyDown(event) => {
+                if event.ctrl_key() || event.meta_key() {
+                    match event.key().as_str() {
+                        "z" => self.undo(),
+                        "c" => self.copy_selection(),
+                        "v" => self.paste_clipboard(),
+                        _ => return false,
+                    }
+                    event.prevent_default();
+                    return false;
+                }
+
+                let transform = match event.key().as_str() {
+                    "r" => Some(life::Transform::Rotate90),
+                    "R" => Some(life::Transform::Rotate270),
+                    "u" => Some(life::Transform::Rotate180),
+                    "h" => Some(life::Transform::FlipHorizontal),
+                    "v" => Some(life::Transform::FlipVertical),
+                    "t" => Some(life::Transform::Transpose),
+                    _ => None,
+                };
+                let offset = match event.key().as_str() {
+                    "ArrowLeft" => Some((-1, 0)),
+                    "ArrowRight" => Some((1, 0)),
+                    "ArrowUp" => Some((0, -1)),
+                    "ArrowDown" => Some((0, 1)),
+                    _ => None,
+                };
+
+                if let Some(transform) = transform {
+                    if self.selection.is_some() {
+                        self.transform_selection(transform);
+                    } else if let Some(stamp) = &self.stamp {
+                        self.stamp = Some(stamp.transform(transform));
+                    }
+                } else if let Some((dx, dy)) = offset {
+                    if self.selection.is_some() {
+                        event.prevent_default();
+                        self.move_selection(dx, dy);
+                    }
+                }
+                false
+            },
         }        
     }
     fn mounted(&mut self) -> ShouldRender {
@@ -419,35 +934,21 @@ impl Component for App {
         };
 
 
-        let patterns = vec![
-                Pattern::ToggleCell, Pattern::Glider, Pattern:: Pulsar,
-                Pattern::Pentadecathlon, Pattern::LWSS, Pattern::MWSS, 
-                Pattern::HWSS, Pattern::GosperGliderGun, Pattern::RPentamino,
-                Pattern::Diehard, Pattern::Acorn, Pattern::Sawtooth1212,
-                Pattern::Homer, Pattern::DRHOscillators,
-                Pattern::C3Ladder, Pattern::C4Ladder,
-                Pattern::QuadraticGrowth,
-                Pattern::P200Oscillator,
-                Pattern::LFODMisc,
-                Pattern::SeedsMisc,
-                Pattern::SimpleReplicator,
-            ];
-
-        let rules = vec! [
-            RuleSet::Conway,
-            RuleSet::LifeWithoutDeath,
-            RuleSet::DayAndNight,
-            RuleSet::LiveFreeOrDie,
-            RuleSet::Replicator,
-            RuleSet::Replicator2,
-            RuleSet::Seeds,
-            RuleSet::Maze,
-            RuleSet::EightLife,
-            RuleSet::LongLife,
-            RuleSet::HighLife,
-            RuleSet::DryLife,
+        let tools = vec![
+            Tool::ToggleCell, Tool::Draw, Tool::Erase,
+            Tool::Line, Tool::Rectangle, Tool::FilledRectangle,
+            Tool::Ellipse, Tool::FilledEllipse,
+            Tool::Select, Tool::Stamp,
         ];
 
+        let rules = RuleSet::all();
+
+        let anchors = vec![Anchor::TopLeft, Anchor::Center];
+        let brush_sizes = vec![BrushSize(1), BrushSize(3), BrushSize(5)];
+        let export_scales = vec![ExportScale(1), ExportScale(2), ExportScale(4), ExportScale(8), ExportScale(16)];
+        let speeds: Vec<Speed> = SPEEDS.iter().map(|&speed| Speed(speed)).collect();
+        let paste_modes = vec![life::PasteMode::Copy, life::PasteMode::Or, life::PasteMode::And, life::PasteMode::Xor];
+
         html! {
                 <div>
                     <div>
@@ -459,19 +960,47 @@ impl Component for App {
                     <button class="game-button" onclick=self.link.callback(|_| Msg::Step)>{ "Step" }</button>
                     <button class="game-button" onclick=self.link.callback(|_| Msg::Randomize)>{ "Randomize" }</button>
                     <button class="game-button" onclick=self.link.callback(|_| Msg::Clear)>{ "Clear" }</button>
-                    <Select<Pattern> selected=Pattern::ToggleCell options=patterns onchange=self.link.callback(|pattern| Msg::SetPattern(pattern))/>
-                    <Select<RuleSet> selected=RuleSet::Conway options=rules onchange=self.link.callback(|rules| Msg::SetRuleSet(rules))/>
-                    { "Speed: "}
-                    <input class="slider" type="range" min=1 max=60 value=DEFAULT_FRAMES_PER_SECOND onchange=self.link.callback(|event| Msg::ChangeSpeed(event))>{ "Speed" }</input>
+                    <button class="game-button" onclick=self.link.callback(|_| Msg::ResetSession)>{ "Reset to blank" }</button>
+                    <button class="game-button" onclick=self.link.callback(|_| Msg::Share)>{ "Share" }</button>
+                    <button class="game-button" onclick=self.link.callback(|_| Msg::SaveSnapshot)>{ "Save snapshot" }</button>
+                    <button class="game-button" onclick=self.link.callback(|_| Msg::ExportPng)>{ "Export PNG" }</button>
+                    <Select<ExportScale> selected=self.export_scale options=export_scales onchange=self.link.callback(|scale| Msg::SetExportScale(scale))/>
+                    <label>
+                        <input type="checkbox" checked=self.export_grid onclick=self.link.callback(|_| Msg::ToggleExportGrid)/>
+                        { "Grid" }
+                    </label>
+                    <button class="game-button" onclick=self.link.callback(|_| Msg::ExportGif)>{ "Export GIF" }</button>
+                    { "Generations: " }
+                    <input type="number" min=1 value=self.animation.generations onchange=self.link.callback(|value| Msg::SetGifGenerations(value))/>
+                    { " per frame: " }
+                    <input type="number" min=1 value=self.animation.per_frame onchange=self.link.callback(|value| Msg::SetGifPerFrame(value))/>
+                    { " delay (ms): " }
+                    <input type="number" min=10 step=10 value=self.animation.delay_ms onchange=self.link.callback(|value| Msg::SetGifDelay(value))/>
+                    <Select<Tool> selected=self.tool.clone() options=tools onchange=self.link.callback(|tool| Msg::SetTool(tool))/>
+                    <input type="search" placeholder="Search patterns" value=&self.search oninput=self.link.callback(|input: InputData| Msg::Search(input.value))/>
+                    { self.view_catalogue() }
+                    <Select<BrushSize> selected=self.brush_size options=brush_sizes onchange=self.link.callback(|brush_size| Msg::SetBrushSize(brush_size))/>
+                    <Select<Anchor> selected=self.anchor options=anchors onchange=self.link.callback(|anchor| Msg::SetAnchor(anchor))/>
+                    <Select<life::PasteMode> selected=self.paste_mode options=paste_modes onchange=self.link.callback(|paste_mode| Msg::SetPasteMode(paste_mode))/>
+                    <Select<RuleSet> selected=self.ruleset.clone() options=rules onchange=self.link.callback(|rules| Msg::SetRuleSet(rules))/>
+                    <Select<Speed> selected=Speed(self.speed) options=speeds onchange=self.link.callback(|speed| Msg::SetSpeed(speed))/>
+                    <input type="file" accept=".rle,.cells,.mc,.conk" onchange=self.link.callback(|files| Msg::PickFile(files))/>
+                    { self.view_message() }
+                    { self.view_stats() }
                     <canvas 
                         ref={self.node_ref.clone()} 
+                        tabindex="0"
+                        ondragover=self.link.callback(|event| Msg::DragOver(event))
+                        ondrop=self.link.callback(|event| Msg::DropFile(event))
+                        onkeydown=self.link.callback(|event| Msg::KeyDown(event))
                         ontouchstart=self.link.callback(|event| Msg::StartZoomOrMove(event))
                         ontouchmove=self.link.callback(|event| Msg::ZoomOrMove(event))
                         ontouchend=self.link.callback(|event| Msg::EndZoomOrMove(event))
                         onmousewheel=self.link.callback(|event| Msg::Zoom(event))
                         onmousedown=self.link.callback(|event| Msg::ToggleOrStartMove(event))
                         onmousemove=self.link.callback(|event| Msg::MaybeMove(event))
-                        onmouseup=self.link.callback(|event| Msg::ToggleOrEndMove(event))>
+                        onmouseup=self.link.callback(|event| Msg::ToggleOrEndMove(event))
+                        onmouseleave=self.link.callback(|_| Msg::LeaveCanvas)>
                             { "This text is displayed if your browser does not support HTML5 Canvas." }
                     </canvas>
                 </div>
@@ -481,6 +1010,442 @@ impl Component for App {
 
 impl App {
 
+    // The catalogue entries matching the search, grouped by rule folder with
+    // the ones for the current rule first. Entries for other rules show theirs.
+    fn view_catalogue(&self) -> Html {
+        let rule = self.ruleset.rule();
+        let groups = self.catalogue.search(&self.search, rule);
+        let placeholder = if groups.is_empty() { "No matching patterns" } else { "Choose a pattern" };
+        let view_entry = |(index, entry): (usize, &Entry)| {
+            let title = match &entry.author {
+                Some(author) => format!("{}\n{}", author, entry.summary()),
+                None => entry.summary().to_string(),
+            };
+            let label = if entry.runs_under(rule) {
+                entry.name.clone()
+            } else {
+                format!("{} ({})", entry.name, entry.rule)
+            };
+            html! {
+                <option value=index selected=self.entry == Some(index) title=title>{ label }</option>
+            }
+        };
+
+        html! {
+            <select onchange=self.link.callback(|change| Msg::SetEntry(change))>
+                <option value="" disabled=true selected=self.entry.is_none()>{ placeholder }</option>
+                { for groups.into_iter().map(|(group, entries)| html! {
+                    <optgroup label=group>
+                        { for entries.into_iter().map(view_entry) }
+                    </optgroup>
+                }) }
+            </select>
+        }
+    }
+
+    // The generation and population, and how much the engine holds.
+    fn view_stats(&self) -> Html {
+        let (generation, population, memory) = match (&self.engine, &self.frame) {
+            (Some(_), Some(frame)) => (frame.generation, frame.population, frame.memory),
+            _ => (self.universe.generation, self.universe.population(), self.universe.memory_stats()),
+        };
+        let rate = if self.paused {
+            String::new()
+        } else {
+            format!(", {:.0} generations/s", self.scheduler.rate(now_ms()))
+        };
+        html! {
+            <p class="stats">
+                { format!(
+                    "Generation {}, population {}{}. Chunks: {} stored, {} empty; tiles: {} live, {} active; {} collections.",
+                    generation, population, rate,
+                    memory.chunks, memory.empty_chunks, memory.tiles, memory.active_tiles, memory.collections,
+                ) }
+            </p>
+        }
+    }
+
+    fn view_message(&self) -> Html {
+        match &self.message {
+            Some(message) => html! { <p class="message">{ message }</p> },
+            None => html! {},
+        }
+    }
+
+    // Makes a loaded or pasted pattern the one placed by clicking.
+    fn hold_pattern(&mut self, pattern: life::Pattern) {
+        self.stamp = Some(pattern);
+        self.entry = None;
+        self.tool = Tool::Stamp;
+        self.selection = None;
+        self.message = None;
+    }
+
+    fn paste_clipboard(&mut self) {
+        let on_text = self.link.callback(|text: JsValue| Msg::PasteText(text.as_string()));
+        let on_error = self.link.callback(|_: JsValue| Msg::PasteText(None));
+        let on_text: PromiseCallback = Closure::once(move |text| on_text.emit(text));
+        let on_error: PromiseCallback = Closure::once(move |error| on_error.emit(error));
+
+        let clipboard = web_sys::window().unwrap().navigator().clipboard();
+        let _ = clipboard.read_text().then2(&on_text, &on_error);
+        // The closures must outlive the promise.
+        self.clipboard_callbacks = Some((on_text, on_error));
+    }
+
+    fn copy_selection(&mut self) {
+        if let Some(selection) = self.selection {
+            let rle = self.universe.get_pattern(selection.x, selection.y, selection.width, selection.height)
+                .to_rle(self.ruleset.rule());
+            let clipboard = web_sys::window().unwrap().navigator().clipboard();
+            let _ = clipboard.write_text(&rle);
+        }
+    }
+
+    fn set_ruleset(&mut self, rules: RuleSet) {
+        let (birth, survival) = life::parse_rule(rules.rule()).expect("the listed rules are valid");
+        self.edit(Edit::SetRules(birth, survival));
+        self.ruleset = rules;
+    }
+
+    fn set_speed(&mut self, speed: u64) {
+        self.scheduler.set_target(if speed == 0 { None } else { Some(speed as f64) });
+        self.speed = speed;
+    }
+
+    // Runs the generations the scheduler plans for this frame: in the worker,
+    // one run at a time, or here without one.
+    fn run_scheduled(&mut self) {
+        if self.paused || self.run_started.is_some() || self.mirror.is_syncing() {
+            return;
+        }
+        let now = now_ms();
+        let generations = self.scheduler.plan(now);
+        if generations == 0 {
+            return;
+        }
+
+        if self.engine.is_none() {
+            for _ in 0..generations {
+                self.universe.step();
+            }
+            let finished = now_ms();
+            self.scheduler.ran(finished, generations, finished - now);
+        } else {
+            self.send(Request::Run(generations));
+            self.run_started = Some(now);
+            self.mirror.ran();
+        }
+        self.schedule_save();
+    }
+
+    // Saves once changes have stopped for SAVE_DELAY_MS. A running simulation
+    // never stops changing, so it is saved at least every SAVE_MAX_WAIT_MS,
+    // unless it runs in the worker, when saving waits for the page to sync.
+    fn schedule_save(&mut self) {
+        if self.storage.is_none() {
+            return;
+        }
+        let now = now_ms();
+        let first = *self.unsaved_since.get_or_insert(now);
+        if self.mirror.is_stale() {
+            return;
+        }
+        // Replacing the task cancels the pending save.
+        if self.save_task.is_none() || now - first < SAVE_MAX_WAIT_MS {
+            let handle = TimeoutService::new().spawn(Duration::from_millis(SAVE_DELAY_MS), self.link.callback(|_| Msg::SaveSession));
+            self.save_task = Some(Box::new(handle));
+        }
+    }
+
+    fn save_session(&mut self) {
+        let (cells_x, cells_y, cells) = self.all_cells();
+        let entry = self.entry.and_then(|index| self.catalogue.get(index))
+            .map(|entry| (entry.group.clone(), entry.name.clone()));
+
+        let session = Session {
+            cells: cells.to_rle(self.ruleset.rule()),
+            cells_x,
+            cells_y,
+            generation: self.universe.generation,
+            ruleset: self.ruleset.clone(),
+            x: self.x,
+            y: self.y,
+            cell_size: self.cell_size,
+            speed: self.speed,
+            tool: self.tool.clone(),
+            stamp: self.stamp.as_ref().map(|stamp| stamp.to_rle(self.ruleset.rule())),
+            entry,
+        };
+        if let Some(storage) = self.storage.as_mut() {
+            storage.store(SESSION_KEY, Json(&session));
+        }
+    }
+
+    fn restore_session(&mut self) {
+        let restored = match self.storage.as_mut() {
+            Some(storage) => storage.restore(SESSION_KEY),
+            None => return,
+        };
+        // Nothing saved yet, or saved by an incompatible version.
+        let Json(session): Json<Result<Session, _>> = restored;
+        let session = match session {
+            Ok(session) => session,
+            Err(_) => return,
+        };
+
+        self.set_ruleset(session.ruleset);
+        self.universe.generation = session.generation;
+        if let Ok(cells) = life::Pattern::from_rle(&session.cells) {
+            self.universe.set_pattern(session.cells_x, session.cells_y, &cells);
+        }
+        self.x = session.x;
+        self.y = session.y;
+        self.cell_size = if session.cell_size.is_finite() {
+            session.cell_size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE)
+        } else {
+            DEFAULT_CELL_SIZE
+        };
+        if session.speed != self.speed {
+            self.set_speed(session.speed);
+        }
+        self.tool = session.tool;
+        self.stamp = session.stamp.and_then(|rle| life::Pattern::from_rle(&rle).ok());
+        self.entry = session.entry.and_then(|(group, name)| self.catalogue.find(&group, &name));
+    }
+
+    // Forgets the saved session and starts over with an empty Conway universe.
+    fn reset_session(&mut self) {
+        if let Some(storage) = self.storage.as_mut() {
+            storage.remove(SESSION_KEY);
+        }
+        self.save_task = None;
+        self.unsaved_since = None;
+        self.universe = life::Universe::new(vec!(3), vec!(2, 3));
+        self.ruleset = RuleSet::Conway;
+        self.x = 0.0;
+        self.y = 0.0;
+        self.cell_size = DEFAULT_CELL_SIZE;
+        self.paused = true;
+        self.set_speed(DEFAULT_SPEED);
+        self.tool = Tool::ToggleCell;
+        self.stamp = None;
+        self.entry = None;
+        self.selection = None;
+        self.undo_stack.clear();
+        self.message = None;
+        self.reload_engine();
+    }
+
+    // Every live cell, as a pattern and the position of its top-left corner.
+    fn all_cells(&mut self) -> (i64, i64, life::Pattern) {
+        match self.universe.bounding_box() {
+            Some((x, y, width, height)) => (x, y, self.universe.get_pattern(x, y, width, height)),
+            None => (0, 0, life::Pattern::from_cells(Vec::new())),
+        }
+    }
+
+    // Puts the current view in the URL fragment and copies the link.
+    fn share(&mut self) {
+        let (cells_x, cells_y, cells) = self.all_cells();
+        let shared = SharedView {
+            cells: cells.to_rle(self.ruleset.rule()).lines().skip(1).collect(),
+            cells_x,
+            cells_y,
+            rule: self.ruleset.rule().to_string(),
+            generation: self.universe.generation,
+            x: self.x,
+            y: self.y,
+            cell_size: self.cell_size,
+            playing: !self.paused,
+        };
+
+        match shared.to_fragment() {
+            Some(fragment) => {
+                let window = web_sys::window().unwrap();
+                let location = window.location();
+                let _ = location.set_hash(&fragment);
+                if let Ok(link) = location.href() {
+                    let _ = window.navigator().clipboard().write_text(&link);
+                }
+                self.message = Some("Link copied to the clipboard.".to_string());
+            },
+            None => self.message = Some("This universe is too large to share as a link.".to_string()),
+        }
+    }
+
+    fn open_shared(&mut self, shared: SharedView) {
+        let ruleset = match RuleSet::from_rule(&shared.rule) {
+            Some(ruleset) => ruleset,
+            None => {
+                self.message = Some(format!("Could not open the shared link: unknown rule {}", shared.rule));
+                return;
+            },
+        };
+        let cells = match life::Pattern::from_rle(&shared.cells) {
+            Ok(cells) => cells,
+            Err(error) => {
+                self.message = Some(format!("Could not open the shared link: {}", error));
+                return;
+            },
+        };
+
+        self.universe = life::Universe::new(vec!(3), vec!(2, 3));
+        self.set_ruleset(ruleset);
+        self.universe.generation = shared.generation;
+        self.universe.set_pattern(shared.cells_x, shared.cells_y, &cells);
+        self.x = shared.x;
+        self.y = shared.y;
+        self.cell_size = shared.cell_size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+        self.paused = !shared.playing;
+        self.undo_stack.clear();
+    }
+
+    // Replaces the universe with a saved snapshot, keeping the view.
+    fn open_snapshot(&mut self, universe: life::Universe) {
+        match RuleSet::from_rule(&universe.rule()) {
+            Some(ruleset) => {
+                self.ruleset = ruleset;
+                self.message = None;
+            },
+            None => self.message = Some(format!("The snapshot uses {}, which is not one of the listed rules.", universe.rule())),
+        }
+        self.universe = universe;
+        self.selection = None;
+        self.undo_stack.clear();
+        self.reload_engine();
+    }
+
+    // Applies an edit here, and in the worker with the next request.
+    fn edit(&mut self, edit: Edit) {
+        edit.apply(&mut self.universe);
+        if self.engine.is_some() {
+            self.edits.push(edit);
+        }
+    }
+
+    // Sends a request to the worker, after the edits made since the last one.
+    fn send(&mut self, request: Request) {
+        self.flush_edits();
+        if let Some(engine) = &self.engine {
+            engine.send(&request);
+        }
+    }
+
+    // Sends a request the page has already applied to its own universe.
+    fn send_mirrored(&mut self, request: Request) {
+        self.mirror.mirrored(&request);
+        self.send(request);
+    }
+
+    fn flush_edits(&mut self) {
+        if !self.edits.is_empty() {
+            let edits = std::mem::take(&mut self.edits);
+            self.send_mirrored(Request::Edit(edits));
+        }
+    }
+
+    // Gives the worker a copy of the page's universe, after it was replaced.
+    fn reload_engine(&mut self) {
+        if self.engine.is_some() {
+            self.edits.clear();
+            self.send_mirrored(Request::Load(self.universe.save()));
+            self.mirror.loaded();
+        }
+    }
+
+    // Asks the worker for its universe, if the page's has fallen behind.
+    fn request_sync(&mut self) {
+        if self.mirror.start_sync() {
+            self.send(Request::Sync);
+        }
+    }
+
+    // Whether a message must wait for the page's universe to catch up with
+    // the worker's: it reads cells, for an export or to save them for undo,
+    // or it is input that came after a message already waiting.
+    fn must_wait(&self, msg: &Msg) -> bool {
+        match msg {
+            Msg::RenderGl | Msg::Engine(_) | Msg::LeavePage | Msg::DragOver(_) | Msg::DropFile(_) | Msg::PickFile(_) => false,
+            _ if !self.deferred.is_empty() => true,
+            _ if !self.mirror.is_stale() => false,
+            Msg::Share | Msg::SaveSnapshot | Msg::ExportPng | Msg::ExportGif => true,
+            Msg::ToggleOrStartMove(event) => {
+                matches!(self.tool, Tool::Draw | Tool::Erase)
+                    && event.button() != MIDDLE_BUTTON && !event.shift_key()
+            },
+            Msg::MaybeMove(_) => self.last_painted.is_some(),
+            Msg::ToggleOrEndMove(_) => {
+                self.shape_start.is_some() || (!self.is_moving && matches!(self.tool, Tool::ToggleCell | Tool::Stamp))
+            },
+            Msg::FileLoaded(_, drop_cell) => drop_cell.is_some(),
+            Msg::KeyDown(_) => self.selection.is_some(),
+            _ => false,
+        }
+    }
+
+    // A waiting event's default action can only be prevented as it happens.
+    fn prevent_default(&self, msg: &Msg) {
+        match msg {
+            Msg::ToggleOrStartMove(event) if event.button() == MIDDLE_BUTTON || event.shift_key() => event.prevent_default(),
+            Msg::KeyDown(event) => {
+                let shortcut = (event.ctrl_key() || event.meta_key()) && ["z", "c", "v"].contains(&event.key().as_str());
+                let arrow = self.selection.is_some() && event.key().starts_with("Arrow");
+                if shortcut || arrow {
+                    event.prevent_default();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // The cells on screen, as x, y, width and height.
+    fn cell_view(&self) -> (i64, i64, i64, i64) {
+        let canvas = self.canvas.as_ref().unwrap();
+        let left = (self.x / self.cell_size).floor() as i64;
+        let top = (self.y / self.cell_size).floor() as i64;
+        let right = ((self.x + canvas.width() as f32) / self.cell_size).ceil() as i64;
+        let bottom = ((self.y + canvas.height() as f32) / self.cell_size).ceil() as i64;
+        (left, top, right - left, bottom - top)
+    }
+
+    // The cells on screen, at the chosen export scale.
+    fn export_view(&self) -> export::View {
+        let (x, y, width, height) = self.cell_view();
+        export::View {
+            x,
+            y,
+            width: width as u32,
+            height: height as u32,
+            scale: self.export_scale.0,
+            grid: self.export_grid,
+        }
+    }
+
+    // Offers bytes to the user as a file download.
+    fn download(&self, name: &str, bytes: &[u8], mime: &str) -> Result<(), JsValue> {
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+        let options = BlobPropertyBag::new();
+        options.set_type(mime);
+        let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        let url = Url::create_object_url_with_blob(&blob)?;
+
+        let document = web_sys::window().unwrap().document().unwrap();
+        let link: HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+        link.set_href(&url);
+        link.set_download(name);
+        link.click();
+        Url::revoke_object_url(&url)
+    }
+
+    fn read_file(&mut self, file: File, drop_cell: Option<(i64, i64)>) {
+        let callback = self.link.callback(move |data| Msg::FileLoaded(data, drop_cell));
+        match ReaderService::new().read_file(file, callback) {
+            Ok(task) => self.reader_task = Some(task),
+            Err(error) => self.message = Some(format!("Could not read file: {}", error)),
+        }
+    }
+
     fn initialize_gl(&mut self, gl: &mut GL) {
         let vertex_code = include_str!("./life.vert");
         let fragment_code = include_str!("./life.frag");
@@ -510,7 +1475,40 @@ impl App {
         self.program = Some(program);
 
         // turn off antialias 
-        gl.get_context_attributes().unwrap().antialias(false);
+        gl.get_context_attributes().unwrap().set_antialias(false);
+
+        self.tiles = Some(self.create_texture_program(gl, include_str!("./tiles.frag"), "u_cell_size"));
+        self.density = Some(self.create_texture_program(gl, include_str!("./density.frag"), "u_block_size"));
+    }
+
+    fn create_texture_program(&self, gl: &mut GL, fragment_code: &str, scale_uniform: &str) -> TextureProgram {
+        let vertex_shader = self.create_shader(gl, GL::VERTEX_SHADER, include_str!("./tiles.vert"));
+        let fragment_shader = self.create_shader(gl, GL::FRAGMENT_SHADER, fragment_code);
+        let program = self.create_program(gl, vertex_shader, fragment_shader);
+
+        // Texels are fetched one at a time, never filtered, and integer
+        // textures are only usable with NEAREST anyway.
+        let texture = gl.create_texture();
+        gl.bind_texture(GL::TEXTURE_2D, texture.as_ref());
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::NEAREST as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::NEAREST as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        let max_texture_size = gl.get_parameter(GL::MAX_TEXTURE_SIZE).ok()
+            .and_then(|size| size.as_f64())
+            .unwrap_or(2048.0) as i64;
+
+        TextureProgram {
+            position_attribute_location: gl.get_attrib_location(&program, "a_position") as u32,
+            resolution: gl.get_uniform_location(&program, "u_resolution"),
+            origin: gl.get_uniform_location(&program, "u_origin"),
+            scale: gl.get_uniform_location(&program, scale_uniform),
+            height: gl.get_uniform_location(&program, "u_height"),
+            color: gl.get_uniform_location(&program, "u_color"),
+            program,
+            texture,
+            max_texture_size,
+        }
     }
 
     fn create_shader(&self, gl: &mut GL, shader_type: u32, shader_source: &str) -> WebGlShader {
@@ -539,15 +1537,28 @@ impl App {
     }
         
     pub fn render_gl(&mut self) {
+        self.resize_gl();
+        let (x, y, width, height) = self.cell_view();
+        let view = (x, y, width, height, self.block_size());
+        if self.engine.is_some() && self.view_sent != Some(view) {
+            self.send(Request::View(x, y, width, height, view.4));
+            self.view_sent = Some(view);
+        }
+        self.flush_edits();
+
         let gl = self.gl.as_ref().unwrap();
         let canvas = self.canvas.as_ref().unwrap();
-        self.resize_gl();
         
         gl.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
         
         gl.clear_color(0.0, 0.0, 0.0, 1.0);
         gl.clear(GL::COLOR_BUFFER_BIT);
 
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+
+        self.draw_cells();
+
         gl.use_program(self.program.as_ref());
 
           // Turn on the attribute
@@ -566,33 +1577,11 @@ impl App {
 
         // set the resolution
         gl.uniform2f(self.resolution_uniform_location.as_ref(), canvas.width() as f32, canvas.height() as f32);
-        // set the color
-        gl.uniform4f (self.color_uniform_location.as_ref(), 0.0, 1.0, 0.0, 1.0);
-
-        let vertices: Vec<f32> = self.collect_cells(self.x, self.y, self.x + canvas.width() as f32, self.y + canvas.height() as f32);
-
-        // Creating a new Float32Array leads to painfully noticeable Garbage Collection
-        // So instead, let's reuse the same array as much as possible.
-        if vertices.len() as u32 > self.vertices.length() {
-            self.vertices = js_sys::Float32Array::new_with_length(vertices.len() as u32 * 2);
-        } else {
-            self.vertices.fill(0.0, vertices.len() as u32, self.vertices.length());
-        }
-        
-        for (idx, value) in vertices.into_iter().enumerate() {
-            self.vertices.set_index(idx as u32, value);
-        }
-
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &self.vertices, GL::STATIC_DRAW);
 
-        // Draw the rectangle
-        let primitive_type = GL::TRIANGLES;
-        let offset = 0;
-        let count = self.vertices.length() as i32 / 2;
+        // The overlays are drawn as rectangles, the ghost translucent in a second color.
+        self.draw_overlay(&self.collect_ghost(), (0.3, 0.6, 1.0, 0.5));
+        self.draw_overlay(&self.collect_selection(), (1.0, 1.0, 1.0, 1.0));
 
-        if count > 0 {
-            gl.draw_arrays(primitive_type, offset, count as i32);
-        }
         let render_frame = self.link.callback(|_| Msg::RenderGl);
         let handle = RenderService::new().request_animation_frame(render_frame);
         // A reference to the new handle must be retained for the next render to run.
@@ -614,602 +1603,288 @@ impl App {
         }
     }
 
-    fn collect_cells(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> Vec<f32> {
-        
-        let mut vertices = Vec::new();
-        let cell_size = self.cell_size;
-        for cell in self.universe.live_cells() {
-            let cell_x1 = cell.0 as f32 * cell_size;
-            let cell_y1 = cell.1 as f32 * cell_size;
-            let cell_x2 = cell_x1 + cell_size;
-            let cell_y2 = cell_y1 + cell_size;
-            if cell_x2 > x1 as f32 && cell_x1 < x2 as f32 && cell_y2 > y1 as f32 && cell_y1 < y2 as f32 {
-                let cell_x1 = cell_x1 as f32 - x1 as f32;
-                let cell_x2 = cell_x2 as f32 - x1 as f32;
-                let cell_y1 = cell_y1 as f32 - y1 as f32;
-                let cell_y2 = cell_y2 as f32 - y1 as f32;
-                vertices.append(&mut vec!(
-                            cell_x1, cell_y1,
-                            cell_x2, cell_y1,
-                            cell_x1, cell_y2,
-                            cell_x1, cell_y2,
-                            cell_x2, cell_y1,
-                            cell_x2, cell_y2));
-                }
-            }
-        vertices
-    }
-}
-
-
-mod life {
-    use fnv::{FnvHashMap, FnvHashSet};
-    use std::ops::{Add, Sub};
-
-    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-    enum CellState {
-        Alive,
-        Dead,
-    }
-
-    enum CellAction {
-        Birth,
-        Death,
-        Toggle,
-        Noop,
-    }
-
-    #[derive(Clone, Copy, PartialEq, Eq)]
-    pub struct Tile(pub u32);
-
-    // x grows to the right
-    // y grows down
-    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-    pub struct TCoord(pub i64, pub i64);
-
-    pub type TMap = FnvHashMap<TCoord, Tile>;
-    type TSet = FnvHashSet<TCoord>;
-
-    struct RuleTable(Box<[u32]>);
-
-    pub struct Universe {
-        pub p01: TMap,
-        pub p10: TMap,
-        active: TSet,
-        next_active: TSet,
-        pub generation: u64,
-        rule_table: RuleTable,
-        garbage_collector: Vec<TCoord>,
+    fn cell_at(&self, mouse_event: &MouseEvent) -> (i64, i64) {
+        let canvas_rect = self.canvas.as_ref().unwrap().get_bounding_client_rect();
+        let x = ((mouse_event.client_x() as f32 + self.x - canvas_rect.left() as f32) / self.cell_size).floor() as i64;
+        let y = ((mouse_event.client_y() as f32 + self.y - canvas_rect.top() as f32) / self.cell_size).floor() as i64;
+        (x, y)
     }
 
-    impl Add for TCoord {
-        type Output = Self;
-
-        fn add(self, other: Self) -> Self {
-            TCoord(self.0.wrapping_add(other.0), self.1.wrapping_add(other.1))
+    fn stamp_origin(&self, x: i64, y: i64, stamp: &life::Pattern) -> (i64, i64) {
+        match self.anchor {
+            Anchor::TopLeft => (x, y),
+            Anchor::Center => (x - stamp.width / 2, y - stamp.height / 2),
         }
     }
 
-    impl Sub for TCoord {
-        type Output = Self;
-
-        fn sub(self, other: Self) -> Self {
-            TCoord(self.0.wrapping_sub(other.0), self.1.wrapping_sub(other.1))
+    fn draw_overlay(&self, vertices: &[f32], color: (f32, f32, f32, f32)) {
+        if vertices.is_empty() {
+            return;
         }
-    }
 
-    impl RuleTable {
-
-        fn new(b: Vec<u32>, s: Vec<u32>) -> RuleTable {
-            let mut table = vec![0; 65536];
-
-            for idx in 0..table.len() {
-                let i = idx as u32;
-                let lr_s = i & 0x777;
-                let lr_b = lr_s & !0x20;
-                let ll_s = i & 0xeee;
-                let ll_b = ll_s & !0x40;
-                let ur_s = i & 0x7770;
-                let ur_b = ur_s & !0x200;
-                let ul_s = i & 0xeee0;
-                let ul_b = ul_s & !0x400;
-                
-                let lr = if (((lr_s & 0x20) != 0) && s.contains(&lr_b.count_ones())) || (lr_s & 0x20) == 0 && b.contains(&lr_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
-
-                let ll = if (((ll_s & 0x40) != 0) && s.contains(&ll_b.count_ones())) || (ll_s & 0x40) == 0 && b.contains(&ll_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
-
-                let ur = if (((ur_s & 0x200) != 0) && s.contains(&ur_b.count_ones())) || (ur_s & 0x200) == 0 && b.contains(&ur_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
-
-                let ul = if (((ul_s & 0x400) != 0) && s.contains(&ul_b.count_ones())) || (ul_s & 0x400) == 0 && b.contains(&ul_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
+        let gl = self.gl.as_ref().unwrap();
+        gl.uniform4f(self.color_uniform_location.as_ref(), color.0, color.1, color.2, color.3);
+        let overlay = js_sys::Float32Array::from(vertices);
+        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &overlay, GL::STREAM_DRAW);
+        gl.draw_arrays(GL::TRIANGLES, 0, vertices.len() as i32 / 2);
+    }
 
-                let result: u32 = lr + (ll << 1) + (ur << 4) + (ul << 5);                
-                table[idx] = result;
+    // Paints the brush along the line between two cells, so fast drags
+    // leave no gaps.
+    fn paint(&mut self, from: (i64, i64), to: (i64, i64)) {
+        let radius = (self.brush_size.0 - 1) / 2;
+        let x = from.0.min(to.0) - radius;
+        let y = from.1.min(to.1) - radius;
+        self.snapshot(x, y, (from.0 - to.0).abs() + 2 * radius + 1, (from.1 - to.1).abs() + 2 * radius + 1);
+
+        for (x, y) in life::line(from, to) {
+            for cell_y in y - radius..=y + radius {
+                for cell_x in x - radius..=x + radius {
+                    if self.tool == Tool::Erase {
+                        self.edit(Edit::KillCell(cell_x, cell_y));
+                    } else {
+                        self.edit(Edit::SetCell(cell_x, cell_y));
+                    }
+                }
             }
-            RuleTable(table.into_boxed_slice())
         }
-
     }
 
-    impl Default for RuleTable {
-
-        fn default() -> RuleTable {
-            RuleTable::new(vec!(3), vec!(2, 3))
+    // Starts a new undo step; snapshots taken until the next one are undone together.
+    fn begin_edit(&mut self) {
+        if self.undo_stack.len() == MAX_UNDO {
+            self.undo_stack.remove(0);
         }
+        self.undo_stack.push(Vec::new());
     }
 
-    impl std::fmt::Debug for Tile {
-
-        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-            write!(f, "{:b}", self.0)
+    fn snapshot(&mut self, x: i64, y: i64, width: i64, height: i64) {
+        let pattern = self.universe.get_pattern(x, y, width, height);
+        if let Some(edit) = self.undo_stack.last_mut() {
+            edit.push(Snapshot { x, y, pattern });
         }
     }
 
-    impl Universe {
-
-        pub fn new(b: Vec<u32>, s: Vec<u32>) -> Universe {
-            let p01 = TMap::default();
-            let p10 = TMap::default();
-            let active = TSet::default();
-            let next_active = TSet::default();
-            let generation = 0;
-            let rule_table = RuleTable::new(b, s);
-            let garbage_collector = Vec::new();
-
-            Universe {
-                p01, p10, active, next_active, generation, rule_table, garbage_collector,
-            }
-        }
-
-        pub fn set_rules(&mut self, b: Vec<u32>, s: Vec<u32>) {
-            self.rule_table = RuleTable::new(b, s);
-
-            if self.generation % 64 == 0 {
-                self.gc();
-            }
-
-            if self.generation % 2 == 0 {
-                for &coord in self.p01.keys() {
-                    self.active.insert(coord);
-                    self.active.insert(coord - TCoord(1, 1));
-                    self.active.insert(coord - TCoord(1, 0));
-                    self.active.insert(coord - TCoord(0, 1));
-                }
-            } else {
-                for &coord in self.p10.keys() {
-                    self.active.insert(coord);
-                    self.active.insert(coord + TCoord(1, 1));
-                    self.active.insert(coord + TCoord(1, 0));
-                    self.active.insert(coord + TCoord(0, 1));
-                }
-            }
-        }
-
-        pub fn clear(&mut self) {
-            self.p01 = TMap::default();
-            self.p10 = TMap::default();
-            self.active = TSet::default();
-        }
-
-        pub fn gc(&mut self) {
-            for (&coord, &tile) in &self.p01 {
-                if tile == Tile(0) {
-                    self.garbage_collector.push(coord);
-                }
-            }
-
-            for tile in &self.garbage_collector {
-                self.p01.remove(tile);
-            }
-            self.garbage_collector.clear();
-
-            for (&coord, &tile) in &self.p10 {
-                if tile == Tile(0) {
-                    self.garbage_collector.push(coord);
-                }
+    fn undo(&mut self) {
+        if let Some(edit) = self.undo_stack.pop() {
+            for snapshot in edit.into_iter().rev() {
+                self.edit(Edit::SetPattern(snapshot.x, snapshot.y, snapshot.pattern));
             }
-
-            for tile in &self.garbage_collector {
-                self.p10.remove(tile);
-            }
-            self.garbage_collector.clear();
-        }
-
-        pub fn step(&mut self) {
-            let is_even = self.generation % 2 == 0;
-            if is_even {
-                self.p01_step();
-            } else {
-                self.p10_step();
-            }
-
-            self.generation += 1;
         }
+    }
 
-        fn p01_step(&mut self) {
-
-            self.next_active.clear();
-            for coord in &self.active {
-                let right_coord = *coord + TCoord(1, 0);
-                let down_coord = *coord + TCoord(0, 1);
-                let downright_coord = *coord + TCoord(1, 1);
-
-                let tile = self.p01.get(coord).cloned().unwrap_or(Tile(0));
-                let right = self.p01.get(&right_coord).cloned().unwrap_or(Tile(0));
-                let down = self.p01.get(&down_coord).cloned().unwrap_or(Tile(0));
-                let downright = self.p01.get(&downright_coord).cloned().unwrap_or(Tile(0));
+    fn transform_selection(&mut self, transform: life::Transform) {
+        let selection = self.selection.unwrap();
+        let pattern = self.universe.get_pattern(selection.x, selection.y, selection.width, selection.height)
+            .transform(transform);
+
+        self.begin_edit();
+        self.snapshot(selection.x, selection.y, selection.width, selection.height);
+        self.snapshot(selection.x, selection.y, pattern.width, pattern.height);
+        self.edit(Edit::ClearRegion(selection.x, selection.y, selection.width, selection.height));
+        // The transformed box can reach past the selection, so paste with or to
+        // keep the cells already there.
+        self.edit(Edit::PastePattern(selection.x, selection.y, pattern.clone(), life::PasteMode::Or));
+        self.selection = Some(Selection { width: pattern.width, height: pattern.height, ..selection });
+    }
 
-                let new_tile = self.p01_calc(tile, right, down, downright);
-                self.p10.insert(*coord, new_tile);
+    fn move_selection(&mut self, dx: i64, dy: i64) {
+        let selection = self.selection.unwrap();
+        let pattern = self.universe.get_pattern(selection.x, selection.y, selection.width, selection.height);
+        let moved = Selection { x: selection.x + dx, y: selection.y + dy, ..selection };
+
+        self.begin_edit();
+        self.snapshot(selection.x, selection.y, selection.width, selection.height);
+        self.snapshot(moved.x, moved.y, moved.width, moved.height);
+        self.edit(Edit::ClearRegion(selection.x, selection.y, selection.width, selection.height));
+        self.edit(Edit::SetPattern(moved.x, moved.y, pattern));
+        self.selection = Some(moved);
+    }
 
-                if tile != new_tile {
-                    self.next_active.insert(*coord);
-                    self.next_active.insert(right_coord);
-                    self.next_active.insert(down_coord);
-                    self.next_active.insert(downright_coord);
-                }
-                
+    // Four one-pixel-wide rectangles around the selection, in canvas coordinates.
+    fn collect_selection(&self) -> Vec<f32> {
+        let mut vertices = Vec::new();
+        if let Some(selection) = self.selection {
+            let x1 = selection.x as f32 * self.cell_size - self.x;
+            let y1 = selection.y as f32 * self.cell_size - self.y;
+            let x2 = (selection.x + selection.width) as f32 * self.cell_size - self.x;
+            let y2 = (selection.y + selection.height) as f32 * self.cell_size - self.y;
+
+            for &(rx1, ry1, rx2, ry2) in &[
+                (x1, y1, x2, y1 + 1.0),
+                (x1, y2 - 1.0, x2, y2),
+                (x1, y1, x1 + 1.0, y2),
+                (x2 - 1.0, y1, x2, y2),
+            ] {
+                vertices.extend_from_slice(&[
+                    rx1, ry1,
+                    rx2, ry1,
+                    rx1, ry2,
+                    rx1, ry2,
+                    rx2, ry1,
+                    rx2, ry2]);
             }
-            std::mem::swap(&mut self.next_active, &mut self.active);
         }
+        vertices
+    }
 
-        fn p10_step(&mut self) {
-            self.next_active.clear();
-            for coord in &self.active {
-                let left_coord = *coord - TCoord(1, 0);
-                let up_coord = *coord - TCoord(0, 1);
-                let upleft_coord = *coord - TCoord(1, 1);
-
-                let tile = self.p10.get(coord).cloned().unwrap_or(Tile(0));
-                let left = self.p10.get(&left_coord).cloned().unwrap_or(Tile(0));
-                let up = self.p10.get(&up_coord).cloned().unwrap_or(Tile(0));
-                let upleft = self.p10.get(&upleft_coord).cloned().unwrap_or(Tile(0));
-
-                let new_tile = self.p10_calc(tile, left, up, upleft);
-                self.p01.insert(*coord, new_tile);
-                if tile != new_tile {
-                    self.next_active.insert(*coord);
-                    self.next_active.insert(left_coord);
-                    self.next_active.insert(up_coord);
-                    self.next_active.insert(upleft_coord);
-                }
-                
-            }
-            std::mem::swap(&mut self.next_active, &mut self.active);
-        }
+    // The current stamp at the cursor, or the shape being dragged out,
+    // in canvas coordinates.
+    fn collect_ghost(&self) -> Vec<f32> {
+        let cells = match (&self.stamp, self.shape_start, self.cursor) {
+            (Some(stamp), _, Some((x, y))) if self.tool == Tool::Stamp => {
+                let (x, y) = self.stamp_origin(x, y, stamp);
+                stamp.cells.iter().map(|&(cell_x, cell_y)| (x + cell_x, y + cell_y)).collect()
+            },
+            (_, Some(start), Some(end)) => self.tool.shape(start, end).unwrap_or_default(),
+            _ => Vec::new(),
+        };
 
-        fn p01_calc(&self, tile: Tile, right: Tile, down: Tile, downright: Tile) -> Tile {
-
-            let center_data = tile.0;
-            let down_data = (center_data << 8) + (down.0 >> 24);
-            let right_data = ((center_data << 2) & 0xcccccccc) + ((right.0 >> 2) & 0x33333333);
-            let downright_data = ((down_data << 2) & 0xcccccccc) + ((((right.0 << 8) + (downright.0 >> 24)) >> 2) & 0x33333333);
-
-            Tile(
-                (self.rule_table.0[(center_data >> 16) as usize] << 26) +
-                (self.rule_table.0[(down_data >> 16) as usize] << 18) +
-                (self.rule_table.0[(center_data & 0xffff) as usize] << 10) +
-                (self.rule_table.0[(down_data & 0xffff) as usize] << 2) +
-                (self.rule_table.0[(right_data >> 16) as usize] << 24) +
-                (self.rule_table.0[(downright_data >> 16) as usize] << 16) +
-                (self.rule_table.0[(right_data & 0xffff) as usize] << 8) +
-                (self.rule_table.0[(downright_data & 0xffff) as usize])
-            )
+        let mut vertices = Vec::new();
+        for (x, y) in cells {
+            let x1 = x as f32 * self.cell_size - self.x;
+            let y1 = y as f32 * self.cell_size - self.y;
+            let x2 = x1 + self.cell_size;
+            let y2 = y1 + self.cell_size;
+            vertices.extend_from_slice(&[
+                x1, y1,
+                x2, y1,
+                x1, y2,
+                x1, y2,
+                x2, y1,
+                x2, y2]);
         }
+        vertices
+    }
 
-        fn p10_calc(&self, tile: Tile, left: Tile, up: Tile, upleft: Tile) -> Tile {
-
-            let center_data = tile.0;
-            let up_data = (center_data >> 8) + (up.0 << 24);
-            let left_data = ((center_data >> 2) & 0x33333333) + ((left.0 << 2) & 0xcccccccc) ;
-            let leftup_data = ((up_data >> 2) & 0x33333333) + ((((left.0 >> 8) + (upleft.0 << 24)) << 2) & 0xcccccccc);
-
-            Tile(
-                (self.rule_table.0[(leftup_data >> 16) as usize] << 26) +
-                (self.rule_table.0[(left_data >> 16) as usize] << 18) +
-                (self.rule_table.0[(leftup_data & 0xffff) as usize] << 10) +
-                (self.rule_table.0[(left_data & 0xffff) as usize] << 2) +
-                (self.rule_table.0[(up_data >> 16) as usize] << 24) +
-                (self.rule_table.0[(center_data >> 16) as usize] << 16) +
-                (self.rule_table.0[(up_data & 0xffff) as usize] << 8) +
-                (self.rule_table.0[(center_data & 0xffff) as usize])
-            )
+    // Cells per side of the blocks drawn as one, a power of two big enough
+    // to cover a pixel when zoomed out past a pixel per cell, otherwise 1.
+    fn block_size(&self) -> i64 {
+        if self.cell_size >= 1.0 {
+            1
+        } else {
+            ((1.0 / self.cell_size).ceil() as u64).min(1 << 62).next_power_of_two() as i64
         }
+    }
 
-        fn perform_cell_action(&mut self, mut x: i64, mut y: i64, action: CellAction) -> CellState {
-            
-            if self.generation % 2 == 1 {
-                x -= 1;
-                y -= 1;
-            }
-
-            let coord_x = if x > 0 {
-                x / 4
-            } else {
-                (x / 4) + ((x % 4) - 3) / 4
-            };
-
-            let coord_y = if y > 0 {
-                y / 8
-            } else {
-                (y / 8) + ((y % 8) - 7) / 8
-            };
-
-            let coord = TCoord(coord_x, coord_y);
-            
-            let mut tile = if self.generation % 2 == 0 {
-                self.p01.get(&coord).cloned().unwrap_or(Tile(0))
-            } else {
-                self.p10.get(&coord).cloned().unwrap_or(Tile(0))
-            };
-
-
-            let t_x = if x >= 0 {
-                x % 4
-            } else {
-                ((x % 4) + 4) % 4
-            };
-
-            let t_y = if y >= 0 {
-                y % 8
-            } else {
-                ((y % 8) + 8) % 8
-            };
-
-            match action {
-                CellAction::Birth => {
-                    tile.0 |= 1 << (31 - 4*t_y - t_x);
-                    if self.generation % 2 == 0 {
-                        self.p01.insert(coord, tile);
-                        self.active.insert(coord);
-                        self.active.insert(coord - TCoord(1, 1));
-                        self.active.insert(coord - TCoord(1, 0));
-                        self.active.insert(coord - TCoord(0, 1));
-                    } else {
-                        self.p10.insert(coord, tile);
-                        self.active.insert(coord);
-                        self.active.insert(coord + TCoord(1, 1));
-                        self.active.insert(coord + TCoord(1, 0));
-                        self.active.insert(coord + TCoord(0, 1));
-                    }           
-                },
-                CellAction::Death => {
-                    tile.0 &= !(1 << (31 - 4*t_y - t_x));
-                    if self.generation % 2 == 0 {
-                        self.p01.insert(coord, tile);
-                        self.active.insert(coord);
-                        self.active.insert(coord - TCoord(1, 1));
-                        self.active.insert(coord - TCoord(1, 0));
-                        self.active.insert(coord - TCoord(0, 1));
-                    } else {
-                        self.p10.insert(coord, tile);
-                        self.active.insert(coord);
-                        self.active.insert(coord + TCoord(1, 1));
-                        self.active.insert(coord + TCoord(1, 0));
-                        self.active.insert(coord + TCoord(0, 1));
-                    }
+    // Draws the worker's latest frame, or the page's own universe without a
+    // worker. A frame keeps the form the worker made it in until the next
+    // arrives, even if the zoom has crossed into or out of blocks since.
+    fn draw_cells(&self) {
+        match (&self.engine, &self.frame) {
+            (Some(_), Some(frame)) => match (&frame.density, self.block_size()) {
+                (Some(density), _) => self.draw_density(density),
+                (None, 1) => self.draw_tiles(&frame.tiles),
+                // Zoomed out since the worker sent single cells, which are
+                // shown as blocks until it sends their density.
+                (None, block) => {
+                    let (x, y, width, height) = self.cell_view();
+                    self.draw_density(&frame.tiles.density(x, y, width, height, block));
                 },
-                CellAction::Toggle => {
-                    tile.0 ^= 1 << (31 - 4*t_y - t_x);
-                    if self.generation % 2 == 0 {
-                        self.p01.insert(coord, tile);
-                        self.active.insert(coord);
-                        self.active.insert(coord - TCoord(1, 1));
-                        self.active.insert(coord - TCoord(1, 0));
-                        self.active.insert(coord - TCoord(0, 1));
-                    } else {
-                        self.p10.insert(coord, tile);
-                        self.active.insert(coord);
-                        self.active.insert(coord + TCoord(1, 1));
-                        self.active.insert(coord + TCoord(1, 0));
-                        self.active.insert(coord + TCoord(0, 1));
-                    }
-                }
-                CellAction::Noop => {}
-            };
-            if tile.0 >> (31 - 4*t_y - t_x) & 1 != 0 {
-                CellState::Alive
-            } else {
-                CellState::Dead
-            }
-        }
-
-        fn get_cell(&mut self, x: i64, y: i64) -> CellState {
-            self.perform_cell_action(x, y, CellAction::Noop)
-        }
-
-        pub fn set_cell(&mut self, x: i64, y: i64) {
-            self.perform_cell_action(x, y, CellAction::Birth);
-        }
-
-        pub fn kill_cell(&mut self, x: i64, y: i64) {
-            self.perform_cell_action(x, y, CellAction::Death);
-        }
-
-        pub fn toggle_cell(&mut self, x: i64, y: i64) {
-            self.perform_cell_action(x, y, CellAction::Toggle);
-        }
-
-        pub fn set_rle(&mut self, mut x: i64, mut y: i64, rle: &str) {
-
-            let start_x = x;
-
-            'rle_loop:
-            for line in rle.lines() {
-                if line.starts_with("#") || line.starts_with("x") {
-                    continue;
-                }
-
-                let mut repeat = None;
-                for c in line.chars() {
-                    match c {
-                        '!' => break 'rle_loop,
-                        'b' | 'B' => {
-                            for _i in 0..repeat.unwrap_or(1) {
-                                self.kill_cell(x, y);
-                                x += 1;
-                            }
-                            repeat = None;
-                        },
-                        '$' => {
-                            for _i in 0..repeat.unwrap_or(1) {
-                                y += 1;
-                            }
-                            x = start_x;
-                            repeat = None;
-                        },
-                        s if s.is_whitespace()  => (),
-                        d if d.is_digit(10) => {
-                            match repeat {
-                                None => repeat = Some(d.to_string().parse::<i32>().unwrap()),
-                                Some(d2) => repeat = Some(d.to_string().parse::<i32>().unwrap()+10*d2),
-                            }
-                        },
-                        _ => {
-                            for _i in 0..repeat.unwrap_or(1) {
-                                self.set_cell(x, y);
-                                x += 1;
-                            }
-                            repeat = None;
-                        }
-                    }
-                }                
-            }
-        }
-
-        pub fn live_cells(&self) -> Vec<(i64, i64)> {
-            let mut cells = Vec::new();
-
-            if self.generation % 2 == 1 {
-                for (coord, cell) in &self.p10 {
-                    let mut cell = cell.0;
-                    let mut num_shifts = 0;
-                    while cell != 0 {
-                        if cell & 1 == 1 {
-                            let x = coord.0 * 4 + (3 - (num_shifts % 4));
-                            let y = coord.1 * 8 + (7 - (num_shifts / 4));
-                            cells.push((x + 1, y + 1));
-                        }
-                        num_shifts += 1;
-                        cell >>= 1;
-                    }
-                }
-            } else {
-                for (coord, cell) in &self.p01 {
-                    let mut cell = cell.0;
-                    let mut num_shifts = 0;
-                    while cell != 0 {
-                        if cell & 1 == 1 {
-                            let x = coord.0 * 4 + (3 - (num_shifts % 4));
-                            let y = coord.1 * 8 + (7 - (num_shifts / 4));
-                            cells.push((x, y));
-                        }
-                        num_shifts += 1;
-                        cell >>= 1;
-                    }
+            },
+            _ => {
+                let (x, y, width, height) = self.cell_view();
+                let tiles = self.universe.visible_tiles(x, y, width, height);
+                match self.block_size() {
+                    1 => self.draw_tiles(&tiles),
+                    block => self.draw_density(&tiles.density(x, y, width, height, block)),
                 }
-            }
-            cells
+            },
         }
-
-        // use include_str! for popular patterns
     }
 
-    impl Default for Universe {
-
-        fn default() -> Universe {
-            let b = vec!(3);
-            let s = vec!(2, 3);
+    // Draws the live cells from a texture of the visible tiles, which
+    // tiles.frag decodes, so the cost follows the size of the canvas rather
+    // than the population.
+    fn draw_tiles(&self, view: &life::TileView) {
+        let gl = self.gl.as_ref().unwrap();
+        let tiles = self.tiles.as_ref().unwrap();
 
-            Universe::new(b, s)
-        }
+        // The tiles covering the canvas, never more than it has room for at a
+        // pixel a cell, and cut to the largest texture allowed.
+        let canvas = self.canvas.as_ref().unwrap();
+        let tile_pixels = 8.0 * self.cell_size.max(1.0);
+        let across = (canvas.width() as f32 / tile_pixels).ceil() as i64 + 1;
+        let down = (canvas.height() as f32 / tile_pixels).ceil() as i64 + 1;
+        let (x, y, width, height) = self.cell_view();
+        let left = (x - view.offset).div_euclid(8);
+        let top = (y - view.offset).div_euclid(8);
+        let right = (x + width - view.offset + 7).div_euclid(8).min(left + across.min(tiles.max_texture_size / 2));
+        let bottom = (y + height - view.offset + 7).div_euclid(8).min(top + down.min(tiles.max_texture_size));
+        let texels = view.pack(left, top, right - left, bottom - top);
+
+        gl.use_program(Some(&tiles.program));
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, tiles.texture.as_ref());
+        gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+        let _ = gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D, 0, GL::RGBA8UI as i32, ((right - left) * 2) as i32, (bottom - top) as i32, 0,
+            GL::RGBA_INTEGER, GL::UNSIGNED_BYTE, Some(&texels));
+
+        self.draw_texture(tiles, (left * 8 + view.offset, top * 8 + view.offset), self.cell_size);
     }
 
-
-    #[cfg(test)]
-    mod test {
-
-        use super::*;
-
-        #[test]
-        fn test_p01_calc() {
-            let universe = Universe::default();
-            let tile = Tile(0xc800_2220);
-            let right = Tile(0x8880_0008);
-            let down = Tile(0xd200_0000);
-            let downright = Tile(0x8000_0000);
-
-            let expected = Tile(0xb000_e2df);
-            let result = universe.p01_calc(tile, right, down, downright);
-
-            assert_eq!(expected, result);
-        }
-
-        #[test]
-        fn test_p10_calc() {
-            let universe = Universe::default();
-            let tile = Tile(0x0044_4013);
-            let left = Tile(0x3100_0013);
-            let up = Tile(0x18);
-            let upleft = Tile(0x81);
-
-            let expected = Tile(0xc0c47009);
-            let result = universe.p10_calc(tile, left, up, upleft);
-
-            assert_eq!(expected, result);
+    // Draws the density of blocks of cells as brightness, a texel per block,
+    // for when cells are smaller than pixels.
+    fn draw_density(&self, density: &life::DensityView) {
+        let gl = self.gl.as_ref().unwrap();
+        let program = self.density.as_ref().unwrap();
+        let width = density.width.min(program.max_texture_size);
+        let height = density.height.min(program.max_texture_size);
+
+        // Rows are cut to the texture's width where they are too long.
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for row in density.levels.chunks(density.width.max(1) as usize).take(height as usize) {
+            texels.extend_from_slice(&row[..width as usize]);
         }
 
-        #[test]
-        fn test_step() {
-            let mut universe = Universe::default();
-            universe.set_cell(1, 1);
-            universe.set_cell(2, 1);
-            universe.set_cell(3, 1);
-            universe.step();
-
-            assert_eq!(CellState::Dead, universe.get_cell(1, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 1));
-            assert_eq!(CellState::Dead, universe.get_cell(3, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 2));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 0));
-
-            universe.step();
-            assert_eq!(CellState::Alive, universe.get_cell(1, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(3, 1));
+        gl.use_program(Some(&program.program));
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, program.texture.as_ref());
+        gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+        let _ = gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D, 0, GL::R8 as i32, width as i32, height as i32, 0,
+            GL::RED, GL::UNSIGNED_BYTE, Some(&texels));
 
-        }
+        let origin = (density.x * density.block + density.offset, density.y * density.block + density.offset);
+        self.draw_texture(program, origin, density.block as f32 * self.cell_size);
+    }
 
-        #[test]
-        fn test_many_steps() {
-            let mut universe = Universe::default();
-            universe.set_cell(0, 0);
-            universe.set_cell(1, 0);
-            universe.set_cell(2, 0);
-            universe.set_cell(2, -1);
-            universe.set_cell(1, -2);
-            
-            for _i in 0..1000 {
-                universe.step();
-            }
+    // Covers the canvas with a texture program, its texture's top left at
+    // the given cell and each texel `scale` pixels across.
+    fn draw_texture(&self, program: &TextureProgram, origin: (i64, i64), scale: f32) {
+        let gl = self.gl.as_ref().unwrap();
+        let canvas = self.canvas.as_ref().unwrap();
 
-            let expected = 5;
-            assert_eq!(expected, universe.p01.values().map(|v| v.0.count_ones()).sum::<u32>());
-        }
+        let (canvas_width, canvas_height) = (canvas.width() as f32, canvas.height() as f32);
+        gl.uniform2f(program.resolution.as_ref(), canvas_width, canvas_height);
+        gl.uniform2f(
+            program.origin.as_ref(),
+            origin.0 as f32 * self.cell_size - self.x,
+            origin.1 as f32 * self.cell_size - self.y);
+        gl.uniform1f(program.scale.as_ref(), scale);
+        gl.uniform1f(program.height.as_ref(), canvas_height);
+        gl.uniform4f(program.color.as_ref(), 0.0, 1.0, 0.0, 1.0);
+
+        // One rectangle over the whole canvas; the shader picks out the live cells.
+        let canvas_rect = js_sys::Float32Array::from(&[
+            0.0, 0.0,
+            canvas_width, 0.0,
+            0.0, canvas_height,
+            0.0, canvas_height,
+            canvas_width, 0.0,
+            canvas_width, canvas_height][..]);
+        gl.bind_buffer(GL::ARRAY_BUFFER, self.position_buffer.as_ref());
+        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &canvas_rect, GL::STREAM_DRAW);
+        gl.enable_vertex_attrib_array(program.position_attribute_location);
+        gl.vertex_attrib_pointer_with_i32(program.position_attribute_location, 2, GL::FLOAT, false, 0, 0);
+        gl.draw_arrays(GL::TRIANGLES, 0, 6);
     }
-}   
\ No newline at end of file
+}
+
+// Milliseconds from the page's clock, for timing steps.
+fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
@@ -0,0 +1,201 @@
+// Runs patterns without a browser, for scripts and CI.
+
+use conk::export;
+use conk::life::{Pattern, Periodicity, Universe};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage:
+    conk-cli run <pattern> [--rule RULE] [--generations N] [--output FILE]
+                           [--gif FILE [--per-frame N] [--delay MS] [--scale N]]
+    conk-cli stats <pattern> [--rule RULE] [--generations N]
+    conk-cli convert <input> <output> [--rule RULE] [--scale N] [--grid]
+    conk-cli identify <pattern> [--rule RULE] [--max-generations N]
+
+Patterns are read from .rle, .cells, .mc or .conk (snapshot) files, and
+converted to .rle, .cells, .conk or .png. The rule comes from --rule, then
+the file, and defaults to B3/S23.";
+
+const DEFAULT_MAX_GENERATIONS: u64 = 10_000;
+
+// Flags that take a value; any other flag is a switch.
+const VALUE_FLAGS: &[&str] = &["rule", "generations", "output", "gif", "per-frame", "delay", "scale", "max-generations"];
+
+struct Args {
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let mut flags = HashMap::new();
+        let mut switches = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(flag) if VALUE_FLAGS.contains(&flag) => {
+                    let value = args.next().ok_or_else(|| format!("--{} needs a value", flag))?;
+                    flags.insert(flag.to_string(), value);
+                },
+                Some(flag) => switches.push(flag.to_string()),
+                None => positional.push(arg),
+            }
+        }
+        Ok(Args { positional, flags, switches })
+    }
+
+    fn number<T: std::str::FromStr>(&self, flag: &str, default: T) -> Result<T, String> {
+        match self.flags.get(flag) {
+            Some(value) => value.parse().map_err(|_| format!("--{} must be a number, not {}", flag, value)),
+            None => Ok(default),
+        }
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional.get(index).map(String::as_str).ok_or_else(|| format!("missing {}", name))
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path).extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+// Every live cell, with the position of its top-left corner.
+fn all_cells(universe: &mut Universe) -> (i64, i64, Pattern) {
+    match universe.bounding_box() {
+        Some((x, y, width, height)) => (x, y, universe.get_pattern(x, y, width, height)),
+        None => (0, 0, Pattern::from_cells(Vec::new())),
+    }
+}
+
+fn run_for(universe: &mut Universe, generations: u64) -> Duration {
+    let start = Instant::now();
+    for _ in 0..generations {
+        universe.step();
+    }
+    start.elapsed()
+}
+
+fn stats(universe: &Universe, generations: u64, elapsed: Duration) -> String {
+    let bounding_box = match universe.bounding_box() {
+        Some((x, y, width, height)) => format!("x = {}, y = {}, width = {}, height = {}", x, y, width, height),
+        None => "empty".to_string(),
+    };
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 { generations as f64 / seconds } else { 0.0 };
+    let memory = universe.memory_stats();
+
+    format!(
+        "rule: {}\ngeneration: {}\npopulation: {}\nbounding box: {}\nchunks: {} ({} empty, {} collections)\ntiles: {} live, {} active\ntime: {:.3} ms ({:.0} generations/s)\n",
+        universe.rule(), universe.generation, universe.population(), bounding_box,
+        memory.chunks, memory.empty_chunks, memory.collections, memory.tiles, memory.active_tiles, seconds * 1000.0, rate,
+    )
+}
+
+fn write(path: &str, contents: &[u8]) -> Result<(), String> {
+    fs::write(path, contents).map_err(|error| format!("could not write {}: {}", path, error))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let mut universe = Universe::open(args.positional(1, "pattern")?, args.flags.get("rule").map(String::as_str))?;
+    let generations = args.number("generations", 0)?;
+
+    if let Some(path) = args.flags.get("gif") {
+        let (x, y, width, height) = universe.bounding_box().unwrap_or((0, 0, 1, 1));
+        let view = export::View { x, y, width: width as u32, height: height as u32, scale: args.number("scale", 4)?, grid: false };
+        let animation = export::Animation {
+            // Frames past export::MAX_FRAMES are refused by animate.
+            generations: u32::try_from(generations).map_err(|_| format!("{} generations is too many for a GIF", generations))?,
+            per_frame: args.number("per-frame", 1)?,
+            delay_ms: args.number("delay", 100)?,
+        };
+        write(path, &export::animate(&universe, &view, &animation)?)?;
+    }
+
+    let elapsed = run_for(&mut universe, generations);
+    let (_, _, pattern) = all_cells(&mut universe);
+    let rle = pattern.to_rle(&universe.rule());
+    match args.flags.get("output") {
+        Some(path) => write(path, rle.as_bytes())?,
+        None => print!("{}", rle),
+    }
+    eprint!("{}", stats(&universe, generations, elapsed));
+    Ok(())
+}
+
+fn print_stats(args: &Args) -> Result<(), String> {
+    let mut universe = Universe::open(args.positional(1, "pattern")?, args.flags.get("rule").map(String::as_str))?;
+    let generations = args.number("generations", 0)?;
+    let elapsed = run_for(&mut universe, generations);
+    print!("{}", stats(&universe, generations, elapsed));
+    Ok(())
+}
+
+fn convert(args: &Args) -> Result<(), String> {
+    let mut universe = Universe::open(args.positional(1, "input")?, args.flags.get("rule").map(String::as_str))?;
+    let output = args.positional(2, "output")?;
+    let (x, y, pattern) = all_cells(&mut universe);
+
+    let contents = match extension(output).as_str() {
+        "rle" => pattern.to_rle(&universe.rule()).into_bytes(),
+        "cells" => pattern.to_plaintext().into_bytes(),
+        "conk" => universe.save(),
+        "png" => {
+            let view = export::View {
+                x,
+                y,
+                width: pattern.width.max(1) as u32,
+                height: pattern.height.max(1) as u32,
+                scale: args.number("scale", 4)?,
+                grid: args.switches.iter().any(|switch| switch == "grid"),
+            };
+            export::rasterise(&universe.live_cells(), &view)?.to_png()
+        },
+        extension => return Err(format!("cannot convert to .{} files", extension)),
+    };
+    write(output, &contents)
+}
+
+fn identify(args: &Args) -> Result<(), String> {
+    let mut universe = Universe::open(args.positional(1, "pattern")?, args.flags.get("rule").map(String::as_str))?;
+    let max_generations = args.number("max-generations", DEFAULT_MAX_GENERATIONS)?;
+
+    match universe.find_period(max_generations) {
+        Some(Periodicity::DiesOut { generation }) => println!("dies out at generation {}", generation),
+        Some(Periodicity::Periodic { start, period: 1, .. }) => println!("still life from generation {}", start),
+        Some(Periodicity::Periodic { start, period, dx: 0, dy: 0 }) => {
+            println!("oscillator with period {} from generation {}", period, start)
+        },
+        Some(Periodicity::Periodic { start, period, dx, dy }) => {
+            println!("spaceship with period {} moving ({}, {}) from generation {}", period, dx, dy, start)
+        },
+        None => println!("no period found within {} generations", max_generations),
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = Args::parse(std::env::args().skip(1)).and_then(|args| {
+        match args.positional.first().map(String::as_str) {
+            Some("run") => run(&args),
+            Some("stats") => print_stats(&args),
+            Some("convert") => convert(&args),
+            Some("identify") => identify(&args),
+            Some(command) => Err(format!("unknown command {}\n\n{}", command, USAGE)),
+            None => Err(USAGE.to_string()),
+        }
+    });
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,278 @@
+// A terminal frontend, for quick looks at patterns over SSH.
+
+use conk::life::Universe;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage:
+    conk-term <pattern> [--rule RULE]
+
+Keys:
+    space        play or pause
+    n            step one generation
+    arrows, hjkl pan
+    - and +      zoom out and in
+    [ and ]      slower and faster
+    b            switch between half blocks and braille
+    c            centre on the pattern
+    ?            show these keys
+    q            quit";
+
+const MIN_DELAY: Duration = Duration::from_millis(1);
+const MAX_DELAY: Duration = Duration::from_millis(2000);
+// Cells per dot when zoomed all the way out, far short of overflowing the
+// view's arithmetic.
+const MAX_SCALE: i64 = 1 << 40;
+
+// How long to wait for a key while paused; only resizes and keys matter.
+const IDLE_POLL: Duration = Duration::from_millis(500);
+
+// Braille dots are numbered down the left column, then the right, with the
+// bottom row added later to the standard.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// Puts the terminal back however the viewer exits.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Viewer {
+    universe: Universe,
+    // The cell drawn in the top-left dot.
+    x: i64,
+    y: i64,
+    // Each dot covers scale x scale cells, and is lit if any of them is alive.
+    scale: i64,
+    braille: bool,
+    playing: bool,
+    delay: Duration,
+}
+
+impl Viewer {
+    // Dots per character, across and down.
+    fn dots(&self) -> (i64, i64) {
+        if self.braille { (2, 4) } else { (1, 2) }
+    }
+
+    // The size of the view in dots, leaving the bottom row for the status line.
+    fn view_size(&self) -> io::Result<(i64, i64)> {
+        let (columns, rows) = terminal::size()?;
+        let (across, down) = self.dots();
+        Ok((i64::from(columns) * across, i64::from(rows.saturating_sub(1)) * down))
+    }
+
+    fn centre(&mut self) -> io::Result<()> {
+        let (width, height) = self.view_size()?;
+        let (x, y, pattern_width, pattern_height) = self.universe.bounding_box().unwrap_or((0, 0, 0, 0));
+        self.x = x + pattern_width / 2 - width * self.scale / 2;
+        self.y = y + pattern_height / 2 - height * self.scale / 2;
+        Ok(())
+    }
+
+    // Zooms about the middle of the view.
+    fn zoom(&mut self, scale: i64) -> io::Result<()> {
+        let (width, height) = self.view_size()?;
+        self.x += width * (self.scale - scale) / 2;
+        self.y += height * (self.scale - scale) / 2;
+        self.scale = scale;
+        Ok(())
+    }
+
+    // Moves a quarter of the view in the given direction.
+    fn pan(&mut self, dx: i64, dy: i64) -> io::Result<()> {
+        let (width, height) = self.view_size()?;
+        self.x += dx * (width * self.scale / 4).max(1);
+        self.y += dy * (height * self.scale / 4).max(1);
+        Ok(())
+    }
+
+    // Returns false when the viewer should quit.
+    fn handle(&mut self, key: KeyEvent) -> io::Result<bool> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char(' ') => self.playing = !self.playing,
+            KeyCode::Char('n') => {
+                self.playing = false;
+                self.universe.step();
+            },
+            KeyCode::Left | KeyCode::Char('h') => self.pan(-1, 0)?,
+            KeyCode::Down | KeyCode::Char('j') => self.pan(0, 1)?,
+            KeyCode::Up | KeyCode::Char('k') => self.pan(0, -1)?,
+            KeyCode::Right | KeyCode::Char('l') => self.pan(1, 0)?,
+            KeyCode::Char('-') => self.zoom((self.scale * 2).min(MAX_SCALE))?,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.zoom((self.scale / 2).max(1))?,
+            KeyCode::Char('[') => self.delay = (self.delay * 2).min(MAX_DELAY),
+            KeyCode::Char(']') => self.delay = (self.delay / 2).max(MIN_DELAY),
+            KeyCode::Char('b') => self.braille = !self.braille,
+            KeyCode::Char('c') => self.centre()?,
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let (width, height) = self.view_size()?;
+        let (across, down) = self.dots();
+
+        let mut dots = vec![false; (width * height) as usize];
+        for (x, y) in self.universe.live_cells() {
+            let column = (x - self.x).div_euclid(self.scale);
+            let row = (y - self.y).div_euclid(self.scale);
+            if column >= 0 && row >= 0 && column < width && row < height {
+                dots[(row * width + column) as usize] = true;
+            }
+        }
+        let lit = |column: i64, row: i64| dots[(row * width + column) as usize];
+
+        // Every line fills the terminal's width, so nothing needs clearing and
+        // frames don't flicker.
+        for row in 0..rows.saturating_sub(1) {
+            let line: String = (0..columns)
+                .map(|column| {
+                    let left = i64::from(column) * across;
+                    let top = i64::from(row) * down;
+                    if self.braille {
+                        let mut bits = 0;
+                        for (dy, dots) in BRAILLE_DOTS.iter().enumerate() {
+                            for (dx, dot) in dots.iter().enumerate() {
+                                if lit(left + dx as i64, top + dy as i64) {
+                                    bits |= dot;
+                                }
+                            }
+                        }
+                        // A blank braille character shows as faint dots in some fonts.
+                        if bits == 0 { ' ' } else { std::char::from_u32(0x2800 + bits).unwrap_or(' ') }
+                    } else {
+                        match (lit(left, top), lit(left, top + 1)) {
+                            (true, true) => '█',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (false, false) => ' ',
+                        }
+                    }
+                })
+                .collect();
+            queue!(out, MoveTo(0, row), Print(line))?;
+        }
+
+        let status = format!(
+            "{} gen {} pop {} | 1:{} | {} ms | {} | ? for keys, q to quit",
+            if self.playing { "playing" } else { "paused" },
+            self.universe.generation,
+            self.universe.population(),
+            self.scale,
+            self.delay.as_millis(),
+            self.universe.rule(),
+        );
+        let status: String = status.chars().chain(std::iter::repeat(' ')).take(usize::from(columns)).collect();
+        queue!(out, MoveTo(0, rows.saturating_sub(1)), Print(status))?;
+        out.flush()
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let _screen = Screen::enter()?;
+        let mut out = io::stdout();
+        let mut last_step = Instant::now();
+
+        self.centre()?;
+        loop {
+            self.draw(&mut out)?;
+
+            let timeout = if self.playing { self.delay.saturating_sub(last_step.elapsed()) } else { IDLE_POLL };
+            if event::poll(timeout)? {
+                match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        if key.code == KeyCode::Char('?') {
+                            self.help(&mut out)?;
+                        } else if !self.handle(key)? {
+                            return Ok(());
+                        }
+                    },
+                    _ => (),
+                }
+            }
+
+            if self.playing && last_step.elapsed() >= self.delay {
+                self.universe.step();
+                last_step = Instant::now();
+            }
+        }
+    }
+
+    // Shows the key bindings until any key is pressed.
+    fn help(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, Clear(ClearType::All))?;
+        for (row, line) in USAGE.lines().enumerate() {
+            queue!(out, MoveTo(0, row as u16), Print(line))?;
+        }
+        out.flush()?;
+        loop {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn load(args: &[String]) -> Result<Universe, String> {
+    let mut path = None;
+    let mut rule = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rule" => rule = Some(args.next().ok_or("--rule needs a value")?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {}\n\n{}", arg, USAGE)),
+        }
+    }
+
+    let path = path.ok_or_else(|| USAGE.to_string())?;
+    Universe::open(path, rule.map(String::as_str))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = load(&args).and_then(|universe| {
+        let mut viewer = Viewer {
+            universe,
+            x: 0,
+            y: 0,
+            scale: 1,
+            braille: false,
+            playing: false,
+            delay: Duration::from_millis(100),
+        };
+        viewer.run().map_err(|error| error.to_string())
+    });
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::life::{format_rule, parse_rule, rle_rule};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Every src/patterns/<rule>/<file>.rle, as (rule folder, file stem, contents).
+const FILES: &[(&str, &str, &str)] = include!(concat!(env!("OUT_DIR"), "/patterns.rs"));
+
+// RLE files without a rule are Conway's Life.
+const DEFAULT_RULE: &str = "B3/S23";
+
+// A built-in pattern and the metadata read from its RLE header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub author: Option<String>,
+    pub description: String,
+    pub group: String,
+    pub rule: String,
+    pub rle: &'static str,
+}
+
+impl Entry {
+    // Reads the #N, #O and #C header lines, naming the entry after its file
+    // when there is no #N line. The rule comes from the "x = ..." line, or
+    // failing that from the folder.
+    pub fn from_rle(group: &str, file: &str, rle: &'static str) -> Entry {
+        let mut name = None;
+        let mut author = None;
+        let mut description = Vec::new();
+
+        for line in rle.lines().take_while(|line| line.starts_with('#')) {
+            let value = line.get(2..).unwrap_or("").trim();
+            match line.get(..2) {
+                Some("#N") if !value.is_empty() => name = Some(value.trim_end_matches(".rle").to_string()),
+                Some("#O") if !value.is_empty() => author = Some(value.to_string()),
+                Some("#C") | Some("#c") => description.push(value),
+                _ => (),
+            }
+        }
+
+        let header_rule = rle_rule(rle).map(|(birth, survival)| format_rule(&birth, &survival));
+        let folder_rule = RuleSet::all().into_iter()
+            .find(|ruleset| ruleset.folder() == group)
+            .map(|ruleset| ruleset.rule().to_string());
+
+        Entry {
+            name: name.unwrap_or_else(|| title_case(file)),
+            author,
+            description: description.join("\n").trim().to_string(),
+            group: group.to_string(),
+            rule: header_rule.or(folder_rule).unwrap_or_else(|| DEFAULT_RULE.to_string()),
+            rle,
+        }
+    }
+
+    pub fn runs_under(&self, rule: &str) -> bool {
+        canonical_rule(rule).as_ref() == Some(&self.rule)
+    }
+
+    // The first line of the description, short enough for a tooltip.
+    pub fn summary(&self) -> &str {
+        self.description.lines().next().unwrap_or("")
+    }
+
+    // Case-insensitive match against the name, author and rule folder.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        query.is_empty()
+            || self.name.to_lowercase().contains(&query)
+            || self.author.as_ref().is_some_and(|author| author.to_lowercase().contains(&query))
+            || self.group.replace('_', " ").contains(&query)
+    }
+}
+
+pub struct Catalogue {
+    entries: Vec<Entry>,
+}
+
+impl Catalogue {
+    pub fn builtin() -> Catalogue {
+        let entries = FILES.iter()
+            .map(|(group, file, rle)| Entry::from_rle(group, file, rle))
+            .collect();
+        Catalogue { entries }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    pub fn find(&self, group: &str, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.group == group && entry.name == name)
+    }
+
+    // Entries matching the query, grouped by rule folder and keeping their
+    // catalogue index. Groups with no matches are left out, and entries that
+    // run under the given rule come first.
+    pub fn search(&self, query: &str, rule: &str) -> Vec<(String, Vec<(usize, &Entry)>)> {
+        let mut groups: Vec<(String, Vec<(usize, &Entry)>)> = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if !entry.matches(query) {
+                continue;
+            }
+            let title = title_case(&entry.group);
+            match groups.iter_mut().find(|(group, _)| *group == title) {
+                Some((_, entries)) => entries.push((index, entry)),
+                None => groups.push((title, vec![(index, entry)])),
+            }
+        }
+
+        for (_, entries) in groups.iter_mut() {
+            entries.sort_by_key(|(_, entry)| !entry.runs_under(rule));
+        }
+        groups.sort_by_key(|(_, entries)| !entries.iter().any(|(_, entry)| entry.runs_under(rule)));
+        groups
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum RuleSet {
+    LifeWithoutDeath,
+    Conway,
+    DayAndNight,
+    LiveFreeOrDie,
+    Replicator,
+    Replicator2,
+    Seeds,
+    Maze,
+    EightLife,
+    LongLife,
+    HighLife,
+    DryLife,
+}
+
+impl RuleSet {
+    pub fn all() -> Vec<RuleSet> {
+        vec![
+            RuleSet::Conway,
+            RuleSet::LifeWithoutDeath,
+            RuleSet::DayAndNight,
+            RuleSet::LiveFreeOrDie,
+            RuleSet::Replicator,
+            RuleSet::Replicator2,
+            RuleSet::Seeds,
+            RuleSet::Maze,
+            RuleSet::EightLife,
+            RuleSet::LongLife,
+            RuleSet::HighLife,
+            RuleSet::DryLife,
+        ]
+    }
+
+    // The preset for a rule written in any notation canonical_rule accepts.
+    pub fn from_rule(rule: &str) -> Option<RuleSet> {
+        let rule = canonical_rule(rule)?;
+        RuleSet::all().into_iter().find(|ruleset| ruleset.rule() == rule)
+    }
+
+    pub fn rule(&self) -> &'static str {
+        match self {
+            RuleSet::Conway => "B3/S23",
+            RuleSet::LifeWithoutDeath => "B3/S012345678",
+            RuleSet::DayAndNight => "B3678/S34678",
+            RuleSet::LiveFreeOrDie => "B2/S0",
+            RuleSet::Replicator => "B1357/S1357",
+            RuleSet::Replicator2 => "B1357/S02468",
+            RuleSet::Seeds => "B2/S",
+            RuleSet::Maze => "B3/S12345",
+            RuleSet::EightLife => "B3/S238",
+            RuleSet::LongLife => "B345/S5",
+            RuleSet::HighLife => "B36/S23",
+            RuleSet::DryLife => "B37/S23",
+        }
+    }
+
+    // The folder under src/patterns for patterns that run under the rule.
+    pub fn folder(&self) -> &'static str {
+        match self {
+            RuleSet::Conway => "conway",
+            RuleSet::LifeWithoutDeath => "life_without_death",
+            RuleSet::DayAndNight => "day_and_night",
+            RuleSet::LiveFreeOrDie => "live_free_or_die",
+            RuleSet::Replicator => "replicator",
+            RuleSet::Replicator2 => "replicator_2",
+            RuleSet::Seeds => "seeds",
+            RuleSet::Maze => "maze",
+            RuleSet::EightLife => "eight_life",
+            RuleSet::LongLife => "long_life",
+            RuleSet::HighLife => "highlife",
+            RuleSet::DryLife => "dry_life",
+        }
+    }
+}
+
+impl fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RuleSet::Conway => "Conway - B3/S23",
+            RuleSet::LifeWithoutDeath => "Life Without Death - B3/S012345678",
+            RuleSet::DayAndNight => "Day & Night - B3678/S34678",
+            RuleSet::LiveFreeOrDie => "Live Free or Die - B2/S0",
+            RuleSet::Replicator => "Replicator - B1357/S1357",
+            RuleSet::Replicator2 => "Replicator 2 - B1357/S02468",
+            RuleSet::Seeds => "Seeds - B2/S",
+            RuleSet::Maze => "Maze - B3/S12345",
+            RuleSet::EightLife => "EightLife - B3/S238",
+            RuleSet::LongLife => "Long Life - B345/S5",
+            RuleSet::HighLife => "HighLife - B36/S23",
+            RuleSet::DryLife => "DryLife - B37/S23",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Writes a rule as "B<digits>/S<digits>" with sorted digits, accepting any
+// notation life::parse_rule does.
+pub fn canonical_rule(rule: &str) -> Option<String> {
+    parse_rule(rule).map(|(birth, survival)| format_rule(&birth, &survival))
+}
+
+// "life_without_death" becomes "Life Without Death".
+pub fn title_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::life::Pattern;
+
+    #[test]
+    fn test_catalogue_patterns() {
+        let catalogue = Catalogue::builtin();
+        for (_, entries) in catalogue.search("", "B3/S23") {
+            for (_, entry) in entries {
+                let pattern = Pattern::from_rle(entry.rle);
+                assert!(pattern.is_ok(), "{} does not parse", entry.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_folders_have_rules() {
+        for (folder, _, _) in FILES {
+            assert!(RuleSet::all().iter().any(|ruleset| ruleset.folder() == *folder), "{} has no rule", folder);
+        }
+    }
+
+    #[test]
+    fn test_from_rle() {
+        let entry = Entry::from_rle("conway", "glider", "#N Glider\n#O Richard K. Guy\n#C The smallest spaceship.\n#C Period 4.\nx = 3, y = 3\nbo$2bo$3o!");
+        assert_eq!(entry.name, "Glider");
+        assert_eq!(entry.author, Some("Richard K. Guy".to_string()));
+        assert_eq!(entry.description, "The smallest spaceship.\nPeriod 4.");
+        assert_eq!(entry.summary(), "The smallest spaceship.");
+
+        assert_eq!(entry.rule, "B3/S23");
+
+        let entry = Entry::from_rle("day_and_night", "p200_oscillator", "#CNo name here\nx = 1, y = 1\no!");
+        assert_eq!(entry.name, "P200 Oscillator");
+        assert_eq!(entry.author, None);
+        assert_eq!(entry.description, "No name here");
+        assert_eq!(entry.rule, "B3678/S34678");
+        assert!(entry.runs_under("b3678/s34678"));
+        assert!(!entry.runs_under("B3/S23"));
+
+        let entry = Entry::from_rle("conway", "replicator", "x = 5, y = 5, rule = 23/36\no!");
+        assert_eq!(entry.rule, "B36/S23");
+    }
+
+    #[test]
+    fn test_canonical_rule() {
+        assert_eq!(canonical_rule("B3/S23"), Some("B3/S23".to_string()));
+        assert_eq!(canonical_rule(" s32/b3 "), Some("B3/S23".to_string()));
+        assert_eq!(canonical_rule("23/3"), Some("B3/S23".to_string()));
+        assert_eq!(canonical_rule("0/2"), Some("B2/S0".to_string()));
+        assert_eq!(canonical_rule("B2/S"), Some("B2/S".to_string()));
+        assert_eq!(canonical_rule("/2"), Some("B2/S".to_string()));
+        assert_eq!(canonical_rule("B9/S23"), None);
+        assert_eq!(canonical_rule("B3S23"), None);
+    }
+
+    #[test]
+    fn test_search() {
+        let catalogue = Catalogue::builtin();
+        let groups = catalogue.search("", "B3/S23");
+        assert_eq!(groups.iter().map(|(_, entries)| entries.len()).sum::<usize>(), catalogue.entries.len());
+        assert!(groups.iter().any(|(group, _)| group == "Life Without Death"));
+        assert_eq!(groups[0].0, "Conway");
+        assert_eq!(catalogue.search("", "B2/S")[0].0, "Seeds");
+
+        let groups = catalogue.search("GLIDER", "B3/S23");
+        assert!(groups.iter().all(|(group, _)| group == "Conway"));
+        assert!(groups[0].1.iter().any(|(index, entry)| entry.name == "Glider" && catalogue.get(*index) == Some(entry)));
+
+        assert!(catalogue.search("conway", "B3/S23").iter().all(|(group, _)| group == "Conway"));
+        assert!(catalogue.search("no such pattern", "B3/S23").is_empty());
+
+        let index = catalogue.find("conway", "Glider").unwrap();
+        assert_eq!(catalogue.get(index).unwrap().name, "Glider");
+        assert_eq!(catalogue.find("seeds", "Glider"), None);
+    }
+}
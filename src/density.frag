@@ -0,0 +1,29 @@
+#version 300 es
+
+precision highp float;
+
+// A texel per block of cells, holding the share of them alive, see
+// life::DensityView.
+uniform sampler2D u_density;
+// Where the top left block of the texture is on the canvas, in pixels.
+uniform vec2 u_origin;
+uniform float u_block_size;
+uniform float u_height;
+uniform vec4 u_color;
+
+out vec4 color;
+
+void main() {
+   vec2 pixel = vec2(gl_FragCoord.x, u_height - gl_FragCoord.y);
+   ivec2 block = ivec2(floor((pixel - u_origin) / u_block_size));
+   if (any(lessThan(block, ivec2(0))) || any(greaterThanEqual(block, textureSize(u_density, 0)))) {
+      discard;
+   }
+
+   float density = texelFetch(u_density, block, 0).r;
+   if (density == 0.0) {
+      discard;
+   }
+   // Sparse blocks are dim but never black, so lone cells still show.
+   color = vec4(u_color.rgb * (0.25 + 0.75 * density), u_color.a);
+}
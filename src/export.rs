@@ -0,0 +1,272 @@
+// Software rendering of live cells into images, so exports are the same
+// whether they come from the browser or the native build.
+
+use crate::life::Universe;
+use std::borrow::Cow;
+
+const BACKGROUND: [u8; 3] = [0, 0, 0];
+const LIVE: [u8; 3] = [0, 255, 0];
+const GRID: [u8; 3] = [48, 48, 48];
+
+// Larger images are refused rather than risking running out of memory.
+pub const MAX_PIXELS: u64 = 1 << 26;
+pub const MAX_FRAMES: u32 = 1000;
+
+// GIF frames index this palette.
+const PALETTE: [[u8; 3]; 3] = [BACKGROUND, LIVE, GRID];
+
+// A region of the universe to draw, in cells. The cell at (x, y) lands in
+// the top-left corner, and each cell becomes a scale x scale square. With
+// grid set, the top and left edges of every cell are drawn as grid lines when
+// cells are at least 3 pixels wide.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+    pub scale: u32,
+    pub grid: bool,
+}
+
+// Which generations go into an animated GIF: every per_frame generations
+// from the current one up to generations more, each shown for delay_ms.
+// The last frame is always the final generation, even when it is fewer
+// than per_frame after the one before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Animation {
+    pub generations: u32,
+    pub per_frame: u32,
+    pub delay_ms: u32,
+}
+
+impl Animation {
+    pub fn frames(&self) -> Result<u32, String> {
+        if self.per_frame == 0 {
+            return Err("generations per frame must be at least 1".to_string());
+        }
+        let frames = self.generations.div_ceil(self.per_frame) + 1;
+        if frames > MAX_FRAMES {
+            return Err(format!("{} frames is too many; the limit is {}", frames, MAX_FRAMES));
+        }
+        Ok(frames)
+    }
+}
+
+// An RGB image, row by row from the top-left pixel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl View {
+    pub fn image_size(&self) -> Result<(u32, u32), String> {
+        let width = u64::from(self.width) * u64::from(self.scale);
+        let height = u64::from(self.height) * u64::from(self.scale);
+        if width == 0 || height == 0 {
+            return Err("the image would be empty".to_string());
+        }
+        if width * height > MAX_PIXELS {
+            return Err(format!("a {}x{} image is too large", width, height));
+        }
+        Ok((width as u32, height as u32))
+    }
+}
+
+impl Image {
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: [u8; 3]) {
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * 3;
+            for pixel in self.pixels[start..start + width as usize * 3].chunks_exact_mut(3) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let start = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[start], self.pixels[start + 1], self.pixels[start + 2]]
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().expect("encoding into memory cannot fail");
+            writer.write_image_data(&self.pixels).expect("the image data matches its size");
+        }
+        png
+    }
+}
+
+// Steps a copy of the universe and draws the view every animation.per_frame
+// generations into a looping GIF.
+pub fn animate(universe: &Universe, view: &View, animation: &Animation) -> Result<Vec<u8>, String> {
+    let frames = animation.frames()?;
+    let (width, height) = view.image_size()?;
+    if width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+        return Err(format!("a {}x{} image is too large for a GIF", width, height));
+    }
+
+    let mut universe = universe.clone();
+    let mut gif = Vec::new();
+    {
+        let palette: Vec<u8> = PALETTE.concat();
+        let mut encoder = gif::Encoder::new(&mut gif, width as u16, height as u16, &palette)
+            .map_err(|error| error.to_string())?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|error| error.to_string())?;
+
+        // GIF delays are in hundredths of a second, so delay_ms is rounded.
+        let delay = ((animation.delay_ms + 5) / 10).min(u32::from(u16::MAX)) as u16;
+        let mut generations = 0;
+        for frame_number in 0..frames {
+            let target = (frame_number * animation.per_frame).min(animation.generations);
+            for _ in generations..target {
+                universe.step();
+            }
+            generations = target;
+            let image = rasterise(&universe.live_cells(), view)?;
+            let indices = image.pixels.chunks_exact(3)
+                .map(|pixel| PALETTE.iter().position(|color| color == pixel).unwrap_or(0) as u8)
+                .collect::<Vec<u8>>();
+
+            let frame = gif::Frame {
+                width: width as u16,
+                height: height as u16,
+                delay,
+                buffer: Cow::Owned(indices),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).map_err(|error| error.to_string())?;
+        }
+    }
+    Ok(gif)
+}
+
+// Draws the cells inside the view; the rest are ignored.
+pub fn rasterise(cells: &[(i64, i64)], view: &View) -> Result<Image, String> {
+    let (width, height) = view.image_size()?;
+    let mut image = Image {
+        width,
+        height,
+        pixels: BACKGROUND.repeat(width as usize * height as usize),
+    };
+
+    let grid = view.grid && view.scale >= 3;
+    if grid {
+        for column in 0..view.width {
+            image.fill(column * view.scale, 0, 1, height, GRID);
+        }
+        for row in 0..view.height {
+            image.fill(0, row * view.scale, width, 1, GRID);
+        }
+    }
+
+    let inset = if grid { 1 } else { 0 };
+    for &(x, y) in cells {
+        let column = x - view.x;
+        let row = y - view.y;
+        if column < 0 || row < 0 || column >= i64::from(view.width) || row >= i64::from(view.height) {
+            continue;
+        }
+        let left = column as u32 * view.scale + inset;
+        let top = row as u32 * view.scale + inset;
+        image.fill(left, top, view.scale - inset, view.scale - inset, LIVE);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn view(scale: u32, grid: bool) -> View {
+        View { x: -2, y: 5, width: 4, height: 3, scale, grid }
+    }
+
+    #[test]
+    fn test_rasterise() {
+        let cells = [(-2, 5), (1, 7), (2, 7), (-3, 5)];
+        let image = rasterise(&cells, &view(2, false)).unwrap();
+        assert_eq!((8, 6), (image.width, image.height));
+        assert_eq!(LIVE, image.pixel(0, 0));
+        assert_eq!(LIVE, image.pixel(1, 1));
+        assert_eq!(BACKGROUND, image.pixel(2, 0));
+        assert_eq!(LIVE, image.pixel(7, 5));
+        assert_eq!(BACKGROUND, image.pixel(5, 5));
+        assert_eq!(image.pixels.chunks(3).filter(|&pixel| pixel == LIVE).count(), 8);
+
+        let image = rasterise(&cells, &view(4, true)).unwrap();
+        assert_eq!(GRID, image.pixel(0, 0));
+        assert_eq!(GRID, image.pixel(4, 2));
+        assert_eq!(LIVE, image.pixel(1, 1));
+        assert_eq!(LIVE, image.pixel(3, 3));
+        assert_eq!(BACKGROUND, image.pixel(5, 1));
+
+        // Too small for grid lines to leave room for the cells.
+        assert_eq!(LIVE, rasterise(&cells, &view(2, true)).unwrap().pixel(0, 0));
+    }
+
+    #[test]
+    fn test_image_size() {
+        assert!(rasterise(&[], &view(0, false)).is_err());
+        assert!(rasterise(&[], &View { x: 0, y: 0, width: 1 << 16, height: 1 << 16, scale: 1, grid: false }).is_err());
+    }
+
+    #[test]
+    fn test_to_png() {
+        let image = rasterise(&[(0, 6)], &view(3, false)).unwrap();
+        let png = image.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        let mut reader = png::Decoder::new(&png[..]).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((12, 9), (info.width, info.height));
+        assert_eq!(image.pixels, &pixels[..info.buffer_size()]);
+    }
+
+    #[test]
+    fn test_animate() {
+        let mut universe = Universe::default();
+        universe.set_cell(-1, 6);
+        universe.set_cell(0, 6);
+        universe.set_cell(1, 6);
+        let animation = Animation { generations: 4, per_frame: 2, delay_ms: 250 };
+        assert_eq!(Ok(3), animation.frames());
+
+        let gif = animate(&universe, &view(2, false), &animation).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(0, universe.generation);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(&gif[..]).unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((8, 6, 25), (frame.width, frame.height, frame.delay));
+            // Both phases of the blinker are three cells inside the view.
+            assert_eq!(3, frame.buffer.iter().filter(|&&index| index == 1).count() / 4);
+            frames += 1;
+        }
+        assert_eq!(3, frames);
+
+        // A remainder still gets a frame, and delays round to the nearest 10ms.
+        let animation = Animation { generations: 5, per_frame: 2, delay_ms: 255 };
+        assert_eq!(Ok(4), animation.frames());
+        let gif = animate(&universe, &view(2, false), &animation).unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(vec!(26, 26, 26, 26), delays);
+
+        assert!(Animation { generations: 4, per_frame: 0, delay_ms: 100 }.frames().is_err());
+        assert!(Animation { generations: MAX_FRAMES, per_frame: 1, delay_ms: 100 }.frames().is_err());
+    }
+}
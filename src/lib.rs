@@ -1,10 +1,23 @@
-#![recursion_limit = "512"]
+#![recursion_limit = "1024"]
 
 mod app;
-mod life;
+mod catalogue;
+pub mod export;
+pub mod life;
+// Only for tests and benchmarks, so it stays out of the app.
+#[cfg(any(test, feature = "bench"))]
+pub mod reference;
+pub mod scheduler;
+mod share;
 mod utils;
+pub mod worker;
 
 use wasm_bindgen::prelude::*;
+
+// Started by static/worker-threads.js before the engine listens, in builds
+// with the `threads` feature.
+#[cfg(all(target_arch = "wasm32", feature = "threads"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
 // This is the entry point for the web app
 #[wasm_bindgen]
 pub fn run_app() {
@@ -12,5 +25,26 @@ pub fn run_app() {
     /*web_logger::init();
     yew::start_app::<app::App>();
     Ok(())*/
-    yew::start_app::<app::App>();
+
+    // A shared link opens the view encoded in its fragment.
+    let fragment = web_sys::window()
+        .and_then(|window| window.location().hash().ok())
+        .unwrap_or_default();
+    let fragment = js_sys::decode_uri_component(fragment.trim_start_matches('#'))
+        .map(String::from)
+        .unwrap_or(fragment);
+    let shared = if fragment.is_empty() {
+        None
+    } else {
+        Some(share::SharedView::from_fragment(&fragment))
+    };
+
+    yew::start_app_with_props::<app::App>(app::Props { shared });
+}
+
+// This is the entry point for the engine's worker, see static/worker.js.
+#[wasm_bindgen]
+pub fn run_worker() {
+    utils::set_panic_hook();
+    worker::listen();
 }
@@ -2,6 +2,9 @@
 // to pick one. mediump is a good default. It means "medium precision"
 precision mediump float;
 
+// Live cells are drawn by tiles.frag and density.frag; this only draws the
+// overlays over them. u_color is switched between draws: the translucent
+// pattern preview, then the selection outline.
 uniform vec4 u_color;
 
 void main() {
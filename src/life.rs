@@ -1,505 +1,2285 @@
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use fnv::{FnvHashMap, FnvHashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::{Add, Sub};
+
+// Snapshots start with this magic and a format version, then a
+// little-endian CRC-32 of the uncompressed body, then the body deflated.
+// All body fields are little-endian:
+//   generation: u64
+//   birth, survival: u16 each, bit n set if n neighbours give birth or survive
+//   parity: u8, 0 if the tiles below are p01 tiles and 1 if p10
+//   tile count: u64
+//   tiles: x: i64, y: i64, bits: u64 for each non-zero tile
+// Version 1 had 4x8 tiles with u32 bits, and is still read.
+const SNAPSHOT_MAGIC: &[u8] = b"CONK";
+const SNAPSHOT_VERSION: u8 = 2;
+const SNAPSHOT_TILE_SIZE: usize = 24;
+const SNAPSHOT_V1_TILE_SIZE: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CellState {
+    Alive,
+    Dead,
+}
+
+enum CellAction {
+    Birth,
+    Death,
+    Toggle,
+    Noop,
+}
+
+// An 8x8 block of cells, row by row from the top-left cell in the highest
+// bit.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile(pub u64);
+
+// x grows to the right
+// y grows down
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TCoord(pub i64, pub i64);
+
+// Tiles are stored in chunks of CHUNK_SIZE x CHUNK_SIZE, hashed by chunk
+// coordinate, so most neighbouring tiles are an array index away and only
+// tiles on a chunk's edge need another lookup.
+const CHUNK_SIZE: i64 = 64;
+const CHUNK_TILES: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+// Steps calculating fewer tiles than this stay on one thread.
+#[cfg(any(not(target_arch = "wasm32"), feature = "threads"))]
+const PARALLEL_MIN_TILES: u32 = 512;
+
+// One bit for each tile of a chunk, a row to a u64 with the leftmost tile in
+// the highest bit.
+type ChunkBits = [u64; CHUNK_SIZE as usize];
+
+#[derive(Clone)]
+struct Chunk {
+    // The p01 tiles, then the p10 tiles, row by row.
+    tiles: [Box<[Tile]>; 2],
+    // Non-zero tiles in both phases.
+    live_tiles: usize,
+    // Tiles the next step calculates, and those the step after it will.
+    active: ChunkBits,
+    next_active: ChunkBits,
+    // Tiles changed by edits since the last step, see mark_edited.
+    edited: ChunkBits,
+}
+
+// Bit n is set if n live neighbours give birth, or let a cell survive.
+#[derive(Clone, Copy)]
+struct RuleMasks {
+    birth: u16,
+    survival: u16,
+}
+
+// One byte lane of every 16 bits, the lanes next_tile works in.
+const LANES: u128 = 0x00ff_00ff_00ff_00ff_00ff_00ff_00ff_00ff;
+
+// Parsed patterns are refused past these limits, so a short file can't
+// ask for more cells than the page has memory.
+const MAX_PATTERN_SIZE: i64 = 1 << 30;
+const MAX_PATTERN_CELLS: usize = 1 << 24;
+// Nodes a macrocell file may expand to, counting the branches on the way
+// down to each leaf.
+const MAX_MACROCELL_VISITS: usize = 1 << 24;
+
+// A pattern as a list of live cells, relative to the top-left
+// corner of its width x height bounding box.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pattern {
+    pub width: i64,
+    pub height: i64,
+    pub cells: Vec<(i64, i64)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+// A macrocell node: an 8x8 leaf packed row by row from the top-left bit,
+// or a 2^level square split into nw, ne, sw and se quadrants.
+#[derive(Clone, Copy)]
+enum MacrocellNode {
+    Leaf(u64),
+    Branch(u32, [usize; 4]),
+}
+
+// How a pasted pattern combines with the cells already in its bounding box.
+// Copy overwrites the box, Or adds live cells, Xor toggles them and And
+// keeps only the cells that are alive in both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PasteMode {
+    Copy,
+    Or,
+    And,
+    Xor,
+}
+
+// Rotations are clockwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Transpose,
+}
+
+// What a pattern settles into: it dies out at some generation, or from
+// generation `start` it repeats every `period` generations, moved by
+// (dx, dy). A still life has period 1 and an oscillator no displacement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Periodicity {
+    DiesOut { generation: u64 },
+    Periodic { start: u64, period: u64, dx: i64, dy: i64 },
+}
+
+// When step removes empty chunks. They pile up wherever anything has
+// passed, such as behind every glider, and each holds 64KB. Collecting costs
+// a pass over all chunks, so the ratio policy leaves universes with fewer
+// than MIN_GC_CHUNKS chunks alone, and only counts the empty ones every
+// GC_CHECK_INTERVAL generations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GcPolicy {
+    Never,
+    // Every this many generations.
+    Interval(u64),
+    // Whenever more than this fraction of the stored chunks are empty.
+    ZeroRatio(f64),
+}
+
+pub const MIN_GC_CHUNKS: usize = 16;
+pub const GC_CHECK_INTERVAL: u64 = 64;
+
+// Chunks stored, how many of them are empty and could be collected, the
+// non-zero tiles in both phases, how many tiles the next step will
+// calculate, and how many collections have run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryStats {
+    pub chunks: usize,
+    pub empty_chunks: usize,
+    pub tiles: usize,
+    pub active_tiles: usize,
+    pub collections: u64,
+}
+
+// The non-zero tiles of one generation in some region. Their cells sit
+// `offset` cells down and right of where the tile coordinates put them,
+// one for p10 tiles.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileView {
+    pub offset: i64,
+    pub tiles: Vec<(TCoord, Tile)>,
+}
+
+// How crowded each square block of `block` x `block` cells is, for drawing
+// when zoomed too far out to show cells one by one. `levels` holds a byte
+// per block, row-major over `width` x `height` blocks from block (x, y):
+// 0 when the block is empty, otherwise its share of live cells scaled to
+// 1..=255. Blocks sit `offset` cells down and right, as in TileView.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DensityView {
+    pub offset: i64,
+    pub block: i64,
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+    pub levels: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct Universe {
+    chunks: FnvHashMap<TCoord, Chunk>,
+    pub generation: u64,
+    birth: Vec<u32>,
+    survival: Vec<u32>,
+    rule_masks: RuleMasks,
+    gc_policy: GcPolicy,
+    collections: u64,
+}
+
+impl Add for TCoord {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        TCoord(self.0.wrapping_add(other.0), self.1.wrapping_add(other.1))
+    }
+}
 
-/*mod life {
-    use fnv::{FnvHashMap, FnvHashSet};
-    use std::ops::{Add, Sub};
-    use web_sys::WebGl2RenderingContext as GL;
-    use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, Element, WebGlBuffer, WebGlProgram, WebGlUniformLocation};
-    use yew::NodeRef;
+impl Sub for TCoord {
+    type Output = Self;
 
-    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-    enum CellState {
-        Alive,
-        Dead,
+    fn sub(self, other: Self) -> Self {
+        TCoord(self.0.wrapping_sub(other.0), self.1.wrapping_sub(other.1))
     }
-    #[derive(Clone, Copy, PartialEq, Eq)]
-    struct Tile(u32);
+}
 
-    // x grows to the right
-    // y grows down
-    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-    struct TCoord(i64, i64);
+impl Chunk {
 
-    type TMap = FnvHashMap<TCoord, Tile>;
-    type TSet = FnvHashSet<TCoord>;
+    fn new() -> Chunk {
+        Chunk {
+            tiles: [vec![Tile(0); CHUNK_TILES].into_boxed_slice(), vec![Tile(0); CHUNK_TILES].into_boxed_slice()],
+            live_tiles: 0,
+            active: [0; CHUNK_SIZE as usize],
+            next_active: [0; CHUNK_SIZE as usize],
+            edited: [0; CHUNK_SIZE as usize],
+        }
+    }
 
-    struct RuleTable(Box<[u32]>);
+    // Stores a tile, keeping the count of live tiles.
+    fn set(&mut self, phase: usize, index: usize, tile: Tile) {
+        let old_tile = std::mem::replace(&mut self.tiles[phase][index], tile);
+        self.live_tiles = self.live_tiles + (tile != Tile(0)) as usize - (old_tile != Tile(0)) as usize;
+    }
 
-    struct Universe {
-        p01: TMap,
-        p10: TMap,
-        active: TSet,
-        generation: u64,
-        rule_table: RuleTable,
-        x: i64,
-        y: i64,
+    // Nothing alive and nothing to calculate, so the chunk can be dropped.
+    fn is_empty(&self) -> bool {
+        self.live_tiles == 0 && self.active.iter().chain(&self.edited).all(|&row| row == 0)
     }
+}
 
-    impl Add for TCoord {
-        type Output = Self;
+// The chunk holding a tile, and the tile's index within it.
+fn locate(coord: TCoord) -> (TCoord, usize) {
+    let chunk = TCoord(coord.0.div_euclid(CHUNK_SIZE), coord.1.div_euclid(CHUNK_SIZE));
+    let index = coord.1.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + coord.0.rem_euclid(CHUNK_SIZE);
+    (chunk, index as usize)
+}
 
-        fn add(self, other: Self) -> Self {
-            TCoord(self.0.wrapping_add(other.0), self.1.wrapping_add(other.1))
-        }
+// A tile's bit in its row of ChunkBits.
+fn tile_bit(index: usize) -> u64 {
+    1 << (63 - index % CHUNK_SIZE as usize)
+}
+
+impl RuleMasks {
+
+    fn new(b: &[u32], s: &[u32]) -> RuleMasks {
+        RuleMasks { birth: counts_mask(b), survival: counts_mask(s) }
     }
+}
+
+impl Pattern {
 
-    impl Sub for TCoord {
-        type Output = Self;
+    pub fn from_rle(rle: &str) -> Result<Pattern, ParseError> {
+        let mut cells = Vec::new();
+        let mut x = 0;
+        let mut y = 0;
+        let mut width = 0;
 
-        fn sub(self, other: Self) -> Self {
-            TCoord(self.0.wrapping_sub(other.0), self.1.wrapping_sub(other.1))
+        'rle_loop:
+        for line in rle.lines() {
+            if line.starts_with('#') || line.starts_with('x') {
+                continue;
+            }
+
+            let mut repeat = None;
+            for c in line.chars() {
+                match c {
+                    '!' => break 'rle_loop,
+                    'b' | 'B' => {
+                        x += repeat.unwrap_or(1);
+                        repeat = None;
+                    },
+                    '$' => {
+                        y += repeat.unwrap_or(1);
+                        x = 0;
+                        repeat = None;
+                    },
+                    s if s.is_whitespace() => (),
+                    d if d.is_ascii_digit() => {
+                        let digit = d.to_digit(10).unwrap() as i64;
+                        repeat = Some(repeat.unwrap_or(0i64).checked_mul(10)
+                            .and_then(|repeat| repeat.checked_add(digit))
+                            .filter(|&repeat| repeat <= MAX_PATTERN_SIZE)
+                            .ok_or_else(|| ParseError("RLE repeat count is too large".to_string()))?);
+                    },
+                    '.' => {
+                        x += repeat.unwrap_or(1);
+                        repeat = None;
+                    },
+                    a if a.is_ascii_alphabetic() => {
+                        if cells.len() + repeat.unwrap_or(1) as usize > MAX_PATTERN_CELLS {
+                            return Err(ParseError("RLE pattern has too many cells".to_string()));
+                        }
+                        for _i in 0..repeat.unwrap_or(1) {
+                            cells.push((x, y));
+                            x += 1;
+                        }
+                        repeat = None;
+                    },
+                    other => return Err(ParseError(format!("unexpected '{}' in RLE", other))),
+                }
+                if x > MAX_PATTERN_SIZE || y > MAX_PATTERN_SIZE {
+                    return Err(ParseError("RLE pattern is too large".to_string()));
+                }
+                width = width.max(x);
+            }
         }
+
+        let height = cells.iter().map(|&(_, cell_y)| cell_y + 1).max().unwrap_or(0);
+        Ok(Pattern { width, height, cells })
     }
 
-    impl RuleTable {
+    // Plaintext (.cells): '!' starts a comment line, 'O' or '*' is alive
+    // and '.' is dead.
+    pub fn from_plaintext(plaintext: &str) -> Result<Pattern, ParseError> {
+        let mut cells = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
+
+        for line in plaintext.lines().filter(|line| !line.starts_with('!')) {
+            for (x, c) in line.trim_end().chars().enumerate() {
+                match c {
+                    'O' | '*' => cells.push((x as i64, height)),
+                    '.' => (),
+                    other => return Err(ParseError(format!("unexpected '{}' in plaintext", other))),
+                }
+                width = width.max(x as i64 + 1);
+            }
+            height += 1;
+        }
 
-        fn new(b: Vec<u32>, s: Vec<u32>) -> RuleTable {
-            let mut table = vec![0; 65512];
+        Ok(Pattern { width, height, cells })
+    }
 
-            for idx in 0..table.len() {
-                let i = idx as u32;
-                let lr_s = i & 0x777;
-                let lr_b = lr_s & !0x20;
-                let ll_s = i & 0xeee;
-                let ll_b = ll_s & !0x40;
-                let ur_s = i & 0x7770;
-                let ur_b = ur_s & !0x200;
-                let ul_s = i & 0xeee0;
-                let ul_b = ul_s & !0x400;
-                
-                let lr = if (((lr_s & 0x20) != 0) && s.contains(&lr_b.count_ones())) || b.contains(&lr_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
+    // Two-state macrocell (.mc), as written by Golly.
+    pub fn from_macrocell(macrocell: &str) -> Result<Pattern, ParseError> {
+        let mut nodes: Vec<MacrocellNode> = Vec::new();
+        // The live cells under each node, so empty subtrees are skipped.
+        let mut populations: Vec<u64> = Vec::new();
 
-                let ll = if (((ll_s & 0x40) != 0) && s.contains(&ll_b.count_ones())) || b.contains(&ll_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
+        for line in macrocell.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('[') || line.starts_with('#') {
+                continue;
+            }
 
-                let ur = if (((ur_s & 0x200) != 0) && s.contains(&ur_b.count_ones())) || b.contains(&ur_b.count_ones()) {
-                    1
-                } else {
-                    0
+            let node = if line.starts_with(['.', '*', '$']) {
+                let (mut x, mut y, mut bits) = (0, 0, 0u64);
+                for c in line.chars() {
+                    match c {
+                        '$' => {
+                            x = 0;
+                            y += 1;
+                            continue;
+                        },
+                        '*' if x < 8 && y < 8 => bits |= 1 << (63 - 8 * y - x),
+                        '.' if x < 8 && y < 8 => (),
+                        _ => return Err(ParseError(format!("bad macrocell leaf '{}'", line))),
+                    }
+                    x += 1;
+                }
+                MacrocellNode::Leaf(bits)
+            } else {
+                let fields: Vec<usize> = line.split_whitespace()
+                    .map(|field| field.parse::<usize>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| ParseError(format!("bad macrocell node '{}'", line)))?;
+
+                let level = match fields.as_slice() {
+                    &[level, _, _, _, _] if (4..63).contains(&level) => level as u32,
+                    _ => return Err(ParseError(format!("bad macrocell node '{}'", line))),
                 };
 
-                let ul = if (((ul_s & 0x400) != 0) && s.contains(&ul_b.count_ones())) || b.contains(&ul_b.count_ones()) {
-                    1
-                } else {
-                    0
-                };
+                for &child in fields[1..].iter().filter(|&&child| child != 0) {
+                    let child_level = match nodes.get(child - 1) {
+                        Some(MacrocellNode::Leaf(_)) => 3,
+                        Some(MacrocellNode::Branch(level, _)) => *level,
+                        None => return Err(ParseError(format!("macrocell node {} is not defined yet", child))),
+                    };
+                    if child_level != level - 1 {
+                        return Err(ParseError(format!("macrocell node '{}' has children of the wrong size", line)));
+                    }
+                }
+                MacrocellNode::Branch(level, [fields[1], fields[2], fields[3], fields[4]])
+            };
+            populations.push(match node {
+                MacrocellNode::Leaf(bits) => u64::from(bits.count_ones()),
+                MacrocellNode::Branch(_, children) => children.iter()
+                    .filter(|&&child| child != 0)
+                    .fold(0, |population, &child| population.saturating_add(populations[child - 1])),
+            });
+            nodes.push(node);
+        }
+
+        // Nodes are numbered from 1; 0 is the empty node. The last node is the root.
+        if populations.last().is_some_and(|&population| population > MAX_PATTERN_CELLS as u64) {
+            return Err(ParseError("macrocell pattern has too many cells".to_string()));
+        }
+        let mut cells = Vec::new();
+        let mut visits = 0;
+        let mut stack = vec![(nodes.len(), 0i64, 0i64)];
+        while let Some((index, x, y)) = stack.pop() {
+            if index == 0 || populations[index - 1] == 0 {
+                continue;
+            }
+            visits += 1;
+            if visits > MAX_MACROCELL_VISITS {
+                return Err(ParseError("macrocell pattern is too deep to expand".to_string()));
+            }
+            match nodes[index - 1] {
+                MacrocellNode::Leaf(bits) => {
+                    for bit in 0..64 {
+                        if bits >> (63 - bit) & 1 == 1 {
+                            cells.push((x + bit % 8, y + bit / 8));
+                        }
+                    }
+                },
+                MacrocellNode::Branch(level, [nw, ne, sw, se]) => {
+                    let half = 1i64 << (level - 1);
+                    stack.push((nw, x, y));
+                    stack.push((ne, x + half, y));
+                    stack.push((sw, x, y + half));
+                    stack.push((se, x + half, y + half));
+                },
+            }
+        }
+
+        let pattern = Pattern::from_cells(cells);
+        if pattern.width > MAX_PATTERN_SIZE || pattern.height > MAX_PATTERN_SIZE {
+            return Err(ParseError("macrocell pattern is too large".to_string()));
+        }
+        Ok(pattern)
+    }
+
+    // Picks the parser from the file extension.
+    pub fn from_file(name: &str, contents: &str) -> Result<Pattern, ParseError> {
+        let extension = name.rsplit('.').next().unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "rle" => Pattern::from_rle(contents),
+            "cells" => Pattern::from_plaintext(contents),
+            "mc" => Pattern::from_macrocell(contents),
+            _ => Err(ParseError(format!("unsupported file type '.{}'", extension))),
+        }
+    }
+
+    // An apgcode in extended Wechsler format, like xs4_33 or xq4_153.
+    // Each character is a column of a five-row strip, lowest bit on top;
+    // w, x and y0..yz skip 2, 3 and 4..39 empty columns, and z starts the
+    // next strip.
+    pub fn from_apgcode(apgcode: &str) -> Result<Pattern, ParseError> {
+        let apgcode = apgcode.trim();
+        let (prefix, code) = apgcode.split_once('_')
+            .ok_or_else(|| ParseError(format!("'{}' is not an apgcode", apgcode)))?;
+        if !(prefix.starts_with("xs") || prefix.starts_with("xp") || prefix.starts_with("xq")) {
+            return Err(ParseError(format!("apgcode prefix '{}' has no cells to decode", prefix)));
+        }
 
-                let result: u32 = lr + (ll << 1) + (ur << 4) + (ul << 5);                
-                table[idx] = result;
+        let mut cells = Vec::new();
+        let (mut x, mut strip) = (0, 0);
+        let mut chars = code.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                'w' => x += 2,
+                'x' => x += 3,
+                'y' => {
+                    let skip = chars.next().and_then(|c| c.to_digit(36))
+                        .ok_or_else(|| ParseError(format!("bad apgcode '{}'", apgcode)))?;
+                    x += 4 + skip as i64;
+                },
+                'z' => {
+                    x = 0;
+                    strip += 1;
+                },
+                c => {
+                    let column = c.to_digit(32).filter(|_| !c.is_ascii_uppercase())
+                        .ok_or_else(|| ParseError(format!("bad apgcode '{}'", apgcode)))?;
+                    for row in 0..5 {
+                        if column >> row & 1 == 1 {
+                            cells.push((x, strip * 5 + row));
+                        }
+                    }
+                    x += 1;
+                },
             }
-            RuleTable(table.into_boxed_slice())
         }
 
+        Ok(Pattern::from_cells(cells))
     }
 
-    impl Default for RuleTable {
+    // Guesses the format of pasted text.
+    pub fn parse(text: &str) -> Result<Pattern, ParseError> {
+        let trimmed = text.trim();
+        let is_apgcode = trimmed.split_once('_')
+            .map(|(prefix, _)| prefix.len() > 2 && prefix[2..].chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false) && !trimmed.contains(char::is_whitespace);
+        let is_plaintext = trimmed.lines()
+            .filter(|line| !line.starts_with('!'))
+            .all(|line| line.trim_end().chars().all(|c| c == '.' || c == 'O' || c == '*'));
+
+        if is_apgcode {
+            Pattern::from_apgcode(trimmed)
+        } else if trimmed.starts_with("[M2]") {
+            Pattern::from_macrocell(trimmed)
+        } else if is_plaintext {
+            Pattern::from_plaintext(trimmed)
+        } else if trimmed.lines().any(|line| line.starts_with('x')) || trimmed.contains('!') {
+            Pattern::from_rle(trimmed)
+        } else {
+            Err(ParseError("not RLE, plaintext, macrocell or an apgcode".to_string()))
+        }
+    }
 
-        fn default() -> RuleTable {
-            RuleTable::new(vec!(3), vec!(2, 3))
+    pub fn to_rle(&self, rule: &str) -> String {
+        let mut rows: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+        for &(x, y) in &self.cells {
+            rows.entry(y).or_default().push(x);
         }
+
+        let mut tokens = Vec::new();
+        let run = |count: i64, tag: char| if count == 1 {
+            tag.to_string()
+        } else {
+            format!("{}{}", count, tag)
+        };
+
+        let mut previous_y = None;
+        for (y, mut xs) in rows {
+            if let Some(previous_y) = previous_y {
+                tokens.push(run(y - previous_y, '$'));
+            } else if y > 0 {
+                tokens.push(run(y, '$'));
+            }
+            previous_y = Some(y);
+
+            xs.sort_unstable();
+            xs.dedup();
+            let mut x = 0;
+            let mut index = 0;
+            while index < xs.len() {
+                let start = xs[index];
+                let mut end = start + 1;
+                index += 1;
+                while index < xs.len() && xs[index] == end {
+                    end += 1;
+                    index += 1;
+                }
+                if start > x {
+                    tokens.push(run(start - x, 'b'));
+                }
+                tokens.push(run(end - start, 'o'));
+                x = end;
+            }
+        }
+        tokens.push("!".to_string());
+
+        // Lines of RLE data should not exceed 70 characters.
+        let mut rle = format!("x = {}, y = {}, rule = {}\n", self.width, self.height, rule);
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length + token.len() > 70 {
+                rle.push('\n');
+                line_length = 0;
+            }
+            line_length += token.len();
+            rle.push_str(&token);
+        }
+        rle.push('\n');
+        rle
     }
 
-    impl std::fmt::Debug for Tile {
+    pub fn to_plaintext(&self) -> String {
+        let mut rows = vec![vec!['.'; self.width as usize]; self.height as usize];
+        for &(x, y) in &self.cells {
+            rows[y as usize][x as usize] = 'O';
+        }
 
-        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-            write!(f, "{:b}", self.0)
+        let mut plaintext = String::new();
+        for row in rows {
+            plaintext.extend(row);
+            plaintext.push('\n');
         }
+        plaintext
     }
 
-    impl Universe {
+    // Builds a pattern from absolute cells, moved so its bounding box
+    // starts at the origin and sorted row by row.
+    pub fn from_cells(mut cells: Vec<(i64, i64)>) -> Pattern {
+        cells.sort_unstable_by_key(|&(x, y)| (y, x));
+
+        if cells.is_empty() {
+            return Pattern { width: 0, height: 0, cells };
+        }
+
+        let min_x = cells.iter().map(|cell| cell.0).min().unwrap();
+        let min_y = cells.iter().map(|cell| cell.1).min().unwrap();
+        let max_x = cells.iter().map(|cell| cell.0).max().unwrap();
+        let max_y = cells.iter().map(|cell| cell.1).max().unwrap();
+
+        Pattern {
+            width: max_x - min_x + 1,
+            height: max_y - min_y + 1,
+            cells: cells.into_iter().map(|(x, y)| (x - min_x, y - min_y)).collect(),
+        }
+    }
 
-        pub fn new(b: Vec<u32>, s: Vec<u32>) -> Universe {
-            let p01 = TMap::default();
-            let p10 = TMap::default();
-            let active = TSet::default();
-            let generation = 0;
-            let rule_table = RuleTable::new(b, s);
-            let x = 0;
-            let y = 0;
+    pub fn transform(&self, transform: Transform) -> Pattern {
+        let (w, h) = (self.width, self.height);
+        let (width, height) = match transform {
+            Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose => (h, w),
+            _ => (w, h),
+        };
+
+        let cells = self.cells.iter().map(|&(x, y)| match transform {
+            Transform::Rotate90 => (h - 1 - y, x),
+            Transform::Rotate180 => (w - 1 - x, h - 1 - y),
+            Transform::Rotate270 => (y, w - 1 - x),
+            Transform::FlipHorizontal => (w - 1 - x, y),
+            Transform::FlipVertical => (x, h - 1 - y),
+            Transform::Transpose => (y, x),
+        }).collect();
+
+        Pattern { width, height, cells }
+    }
+}
+
+// Bresenham's line, including both endpoints.
+pub fn line(start: (i64, i64), end: (i64, i64)) -> Vec<(i64, i64)> {
+    let (mut x, mut y) = start;
+    let dx = (end.0 - x).abs();
+    let dy = -(end.1 - y).abs();
+    let step_x = if x < end.0 { 1 } else { -1 };
+    let step_y = if y < end.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    let mut cells = Vec::new();
+    loop {
+        cells.push((x, y));
+        if (x, y) == end {
+            break;
+        }
 
-            Universe {
-                p01, p10, active, generation, rule_table, x, y
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    cells
+}
+
+pub fn rectangle(start: (i64, i64), end: (i64, i64), filled: bool) -> Vec<(i64, i64)> {
+    let (x1, x2) = (start.0.min(end.0), start.0.max(end.0));
+    let (y1, y2) = (start.1.min(end.1), start.1.max(end.1));
+
+    let mut cells = Vec::new();
+    for y in y1..=y2 {
+        for x in x1..=x2 {
+            if filled || x == x1 || x == x2 || y == y1 || y == y2 {
+                cells.push((x, y));
             }
         }
+    }
+    cells
+}
+
+// The ellipse inscribed in the rectangle between two corners. A cell is
+// inside when its center is; the outline is the inside cells with a
+// neighbor outside.
+pub fn ellipse(start: (i64, i64), end: (i64, i64), filled: bool) -> Vec<(i64, i64)> {
+    let (x1, x2) = (start.0.min(end.0), start.0.max(end.0));
+    let (y1, y2) = (start.1.min(end.1), start.1.max(end.1));
+    let radius_x = (x2 - x1 + 1) as f64 / 2.0;
+    let radius_y = (y2 - y1 + 1) as f64 / 2.0;
+    let center_x = x1 as f64 + radius_x;
+    let center_y = y1 as f64 + radius_y;
+
+    let inside = |x: i64, y: i64| {
+        let dx = (x as f64 + 0.5 - center_x) / radius_x;
+        let dy = (y as f64 + 0.5 - center_y) / radius_y;
+        dx * dx + dy * dy <= 1.0
+    };
+
+    let mut cells = Vec::new();
+    for y in y1..=y2 {
+        for x in x1..=x2 {
+            if inside(x, y) && (filled || !inside(x - 1, y) || !inside(x + 1, y) || !inside(x, y - 1) || !inside(x, y + 1)) {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+// Reads a rule in B/S notation in either order and case ("B3/S23",
+// "s23/b3") or the older S/B digits-only form ("23/3"), returning the
+// birth and survival neighbour counts.
+pub fn parse_rule(rule: &str) -> Option<(Vec<u32>, Vec<u32>)> {
+    let rule = rule.trim().to_uppercase();
+    let parts: Vec<&str> = rule.split('/').map(|part| part.trim()).collect();
+    if parts.len() != 2 {
+        return None;
+    }
 
-        pub fn step(&mut self) {
-            let is_even = self.generation % 2 == 0;
-            if is_even {
-                let (active, p10) = self.p01_step(&self.active, &self.p01);
-                self.active = active;
-                self.p10 = p10;
-            } else {
-                let (active, p01) = self.p10_step(&self.active, &self.p10);
-                self.active = active;
-                self.p01 = p01;
+    let (birth, survival) = match (parts[0].chars().next(), parts[1].chars().next()) {
+        (Some('B'), Some('S')) => (&parts[0][1..], &parts[1][1..]),
+        (Some('S'), Some('B')) => (&parts[1][1..], &parts[0][1..]),
+        (Some('B'), None) => (&parts[0][1..], ""),
+        (Some('S'), None) => ("", &parts[0][1..]),
+        _ => (parts[1], parts[0]),
+    };
+
+    let counts = |digits: &str| -> Option<Vec<u32>> {
+        let mut counts = digits.chars().map(|digit| digit.to_digit(10).filter(|&count| count <= 8)).collect::<Option<Vec<u32>>>()?;
+        counts.sort_unstable();
+        counts.dedup();
+        Some(counts)
+    };
+    Some((counts(birth)?, counts(survival)?))
+}
+
+// Writes a rule in B/S notation with sorted counts, such as "B3/S23".
+pub fn format_rule(birth: &[u32], survival: &[u32]) -> String {
+    let digits = |counts: &[u32]| {
+        let mut counts = counts.to_vec();
+        counts.sort_unstable();
+        counts.dedup();
+        counts.iter().map(|count| count.to_string()).collect::<String>()
+    };
+    format!("B{}/S{}", digits(birth), digits(survival))
+}
+
+// The rule named by an RLE header line such as "x = 3, y = 3, rule = B3/S23".
+pub fn rle_rule(rle: &str) -> Option<(Vec<u32>, Vec<u32>)> {
+    rle.lines()
+        .find(|line| line.trim_start().starts_with('x'))
+        .and_then(|line| line.split(',').find_map(|field| {
+            let mut parts = field.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim() == "rule" => parse_rule(value),
+                _ => None,
             }
+        }))
+}
 
-            self.generation += 1;
+impl fmt::Display for ParseError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::fmt::Debug for Tile {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:b}", self.0)
+    }
+}
+
+impl Universe {
+
+    pub fn new(b: Vec<u32>, s: Vec<u32>) -> Universe {
+        let chunks = FnvHashMap::default();
+        let generation = 0;
+        let rule_masks = RuleMasks::new(&b, &s);
+
+        Universe {
+            chunks, generation, birth: b, survival: s, rule_masks,
+            gc_policy: GcPolicy::ZeroRatio(0.5), collections: 0,
         }
+    }
 
-        fn p01_step(&self, active: &TSet, tiles: &TMap) -> (TSet, TMap) {
-            let mut next_active = TSet::default();
-            let mut next_gen = TMap::default();
+    pub fn set_rules(&mut self, b: Vec<u32>, s: Vec<u32>) {
+        self.rule_masks = RuleMasks::new(&b, &s);
+        self.birth = b;
+        self.survival = s;
 
-            for coord in active {
-                let right_coord = *coord + TCoord(1, 0);
-                let down_coord = *coord + TCoord(0, 1);
-                let downright_coord = *coord + TCoord(1, 1);
+        if self.generation.is_multiple_of(64) {
+            self.gc();
+        }
 
-                let tile = tiles.get(coord).cloned().unwrap_or(Tile(0));
-                let right = tiles.get(&right_coord).cloned().unwrap_or(Tile(0));
-                let down = tiles.get(&down_coord).cloned().unwrap_or(Tile(0));
-                let downright = tiles.get(&downright_coord).cloned().unwrap_or(Tile(0));
+        self.activate_all();
+    }
+
+    // The rule in B/S notation, such as "B3/S23".
+    pub fn rule(&self) -> String {
+        format_rule(&self.birth, &self.survival)
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let parity = self.phase() as u8;
+        let tiles = self.tiles();
+
+        let mut body = Vec::with_capacity(21 + tiles.len() * SNAPSHOT_TILE_SIZE);
+        body.extend_from_slice(&self.generation.to_le_bytes());
+        body.extend_from_slice(&counts_mask(&self.birth).to_le_bytes());
+        body.extend_from_slice(&counts_mask(&self.survival).to_le_bytes());
+        body.push(parity);
+        body.extend_from_slice(&(tiles.len() as u64).to_le_bytes());
+        for (coord, tile) in tiles {
+            body.extend_from_slice(&coord.0.to_le_bytes());
+            body.extend_from_slice(&coord.1.to_le_bytes());
+            body.extend_from_slice(&tile.0.to_le_bytes());
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).expect("deflating into memory cannot fail");
+        let compressed = encoder.finish().expect("deflating into memory cannot fail");
+
+        let mut snapshot = Vec::with_capacity(9 + compressed.len());
+        snapshot.extend_from_slice(SNAPSHOT_MAGIC);
+        snapshot.push(SNAPSHOT_VERSION);
+        snapshot.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        snapshot.extend_from_slice(&compressed);
+        snapshot
+    }
+
+    // Reads a .conk snapshot, or a pattern file placed with its top-left
+    // corner at the origin under the rule in its RLE header, defaulting to
+    // Conway's Life.
+    pub fn from_file(name: &str, contents: &[u8]) -> Result<Universe, ParseError> {
+        let extension = name.rsplit('.').next().unwrap_or("").to_lowercase();
+        if extension == "conk" {
+            return Universe::load(contents);
+        }
+
+        let text = std::str::from_utf8(contents).map_err(|_| ParseError("not a text file".to_string()))?;
+        let pattern = Pattern::from_file(name, text)?;
+        let (birth, survival) = rle_rule(text)
+            .filter(|_| extension == "rle")
+            .unwrap_or((vec!(3), vec!(2, 3)));
+
+        let mut universe = Universe::new(birth, survival);
+        universe.set_pattern(0, 0, &pattern);
+        Ok(universe)
+    }
+
+    // Reads a file as from_file does, replacing its rule if one is given,
+    // for the command line tools.
+    pub fn open(path: &str, rule: Option<&str>) -> Result<Universe, String> {
+        let contents = std::fs::read(path).map_err(|error| format!("could not read {}: {}", path, error))?;
+        let mut universe = Universe::from_file(path, &contents).map_err(|error| format!("could not read {}: {}", path, error))?;
+        if let Some(rule) = rule {
+            let (birth, survival) = parse_rule(rule).ok_or_else(|| format!("{} is not a rule", rule))?;
+            universe.set_rules(birth, survival);
+        }
+        Ok(universe)
+    }
+
+    pub fn load(snapshot: &[u8]) -> Result<Universe, ParseError> {
+        if snapshot.len() < 9 || &snapshot[..4] != SNAPSHOT_MAGIC {
+            return Err(ParseError("not a snapshot".to_string()));
+        }
+        let version = snapshot[4];
+        if version != SNAPSHOT_VERSION && version != 1 {
+            return Err(ParseError(format!("unsupported snapshot version {}", version)));
+        }
+        let checksum = u32::from_le_bytes(snapshot[5..9].try_into().unwrap());
+
+        let mut body = Vec::new();
+        DeflateDecoder::new(&snapshot[9..]).read_to_end(&mut body)
+            .map_err(|_| ParseError("the snapshot is corrupt".to_string()))?;
+        if crc32fast::hash(&body) != checksum {
+            return Err(ParseError("the snapshot checksum does not match".to_string()));
+        }
 
-                let new_tile = self.p01_calc(tile, right, down, downright);
-                next_gen.insert(*coord, new_tile);
+        let mut rest = &body[..];
+        let mut take = |length: usize| -> Result<&[u8], ParseError> {
+            if rest.len() < length {
+                return Err(ParseError("the snapshot is truncated".to_string()));
+            }
+            let (taken, remaining) = rest.split_at(length);
+            rest = remaining;
+            Ok(taken)
+        };
+
+        let generation = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let counts = |mask: u16| (0..=8).filter(|count| mask & 1 << count != 0).collect::<Vec<u32>>();
+        let birth = counts(u16::from_le_bytes(take(2)?.try_into().unwrap()));
+        let survival = counts(u16::from_le_bytes(take(2)?.try_into().unwrap()));
+        let parity = take(1)?[0];
+        if u64::from(parity) != generation % 2 {
+            return Err(ParseError(format!("parity {} does not match generation {}", parity, generation)));
+        }
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+
+        let mut universe = Universe::new(birth, survival);
+        universe.generation = generation;
+        for _ in 0..count {
+            if version == 1 {
+                let tile = take(SNAPSHOT_V1_TILE_SIZE)?;
+                let x = i64::from_le_bytes(tile[0..8].try_into().unwrap());
+                let y = i64::from_le_bytes(tile[8..16].try_into().unwrap());
+                let bits = u32::from_le_bytes(tile[16..20].try_into().unwrap());
+                // Version 1 tiles were 4x8, shifted like today's tiles.
+                for bit in (0..32).filter(|bit| bits & 1 << (31 - bit) != 0) {
+                    let cell_x = x * 4 + bit % 4 + i64::from(parity);
+                    let cell_y = y * 8 + bit / 4 + i64::from(parity);
+                    universe.set_cell(cell_x, cell_y);
+                }
+            } else {
+                let tile = take(SNAPSHOT_TILE_SIZE)?;
+                let x = i64::from_le_bytes(tile[0..8].try_into().unwrap());
+                let y = i64::from_le_bytes(tile[8..16].try_into().unwrap());
+                let bits = u64::from_le_bytes(tile[16..24].try_into().unwrap());
+                universe.put_tile(TCoord(x, y), Tile(bits));
+            }
+        }
+        if !rest.is_empty() {
+            return Err(ParseError("the snapshot has trailing data".to_string()));
+        }
+
+        universe.activate_all();
+        Ok(universe)
+    }
 
-                if tile != new_tile {
-                    next_active.insert(*coord);
-                    next_active.insert(right_coord);
-                    next_active.insert(down_coord);
-                    next_active.insert(downright_coord);
+    // Marks every live tile of either generation, and the tiles around it,
+    // as edited, so the next two steps calculate everything they depend on.
+    fn activate_all(&mut self) {
+        let mut coords = Vec::new();
+        for (&chunk_coord, chunk) in &self.chunks {
+            for (index, (&p01, &p10)) in chunk.tiles[0].iter().zip(chunk.tiles[1].iter()).enumerate() {
+                if p01 != Tile(0) || p10 != Tile(0) {
+                    let x = chunk_coord.0 * CHUNK_SIZE + index as i64 % CHUNK_SIZE;
+                    let y = chunk_coord.1 * CHUNK_SIZE + index as i64 / CHUNK_SIZE;
+                    coords.push(TCoord(x, y));
                 }
-                
             }
-            (next_active, next_gen)
         }
+        for coord in coords {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    self.mark_edited(coord + TCoord(dx, dy));
+                }
+            }
+        }
+    }
+
+    // Records that the tile at coord in the current phase was changed by
+    // something other than a step. The tiles computed from it become active,
+    // and the tile itself is recomputed by the step after next, since its
+    // stored value no longer follows from the other phase.
+    fn mark_edited(&mut self, coord: TCoord) {
+        let direction = if self.generation.is_multiple_of(2) { -1 } else { 1 };
+        for &offset in &[TCoord(0, 0), TCoord(direction, 0), TCoord(0, direction), TCoord(direction, direction)] {
+            let (chunk, index) = self.chunk_mut(coord + offset);
+            chunk.active[index / CHUNK_SIZE as usize] |= tile_bit(index);
+        }
+        let (chunk, index) = self.chunk_mut(coord);
+        chunk.edited[index / CHUNK_SIZE as usize] |= tile_bit(index);
+    }
 
-        fn p10_step(&self, active: &TSet, tiles: &TMap) -> (TSet, TMap) {
-            let mut next_active = TSet::default();
-            let mut next_gen = TMap::default();
+    // The chunk holding a tile, created if need be, and the tile's index.
+    fn chunk_mut(&mut self, coord: TCoord) -> (&mut Chunk, usize) {
+        let (chunk, index) = locate(coord);
+        (self.chunks.entry(chunk).or_insert_with(Chunk::new), index)
+    }
 
-            for coord in active {
-                let left_coord = *coord - TCoord(1, 0);
-                let up_coord = *coord - TCoord(0, 1);
-                let upleft_coord = *coord - TCoord(1, 1);
+    // 0 when the current generation is in p01 tiles, and 1 for p10.
+    fn phase(&self) -> usize {
+        (self.generation % 2) as usize
+    }
 
-                let tile = tiles.get(coord).cloned().unwrap_or(Tile(0));
-                let left = tiles.get(&left_coord).cloned().unwrap_or(Tile(0));
-                let up = tiles.get(&up_coord).cloned().unwrap_or(Tile(0));
-                let upleft = tiles.get(&upleft_coord).cloned().unwrap_or(Tile(0));
+    // A tile of the current generation.
+    fn tile(&self, coord: TCoord) -> Tile {
+        let (chunk, index) = locate(coord);
+        self.chunks.get(&chunk).map_or(Tile(0), |chunk| chunk.tiles[self.phase()][index])
+    }
 
-                let new_tile = self.p10_calc(tile, left, up, upleft);
-                next_gen.insert(*coord, new_tile);
-                if tile != new_tile {
-                    next_active.insert(*coord);
-                    next_active.insert(left_coord);
-                    next_active.insert(up_coord);
-                    next_active.insert(upleft_coord);
+    // Stores a tile of the current generation.
+    fn put_tile(&mut self, coord: TCoord, tile: Tile) {
+        let phase = self.phase();
+        if tile == Tile(0) && !self.chunks.contains_key(&locate(coord).0) {
+            return;
+        }
+        let (chunk, index) = self.chunk_mut(coord);
+        chunk.set(phase, index, tile);
+    }
+
+    // The non-zero tiles of the current generation, by row, then column.
+    fn tiles(&self) -> Vec<(TCoord, Tile)> {
+        let phase = self.phase();
+        let mut tiles = Vec::new();
+        for (&chunk_coord, chunk) in self.chunks.iter().filter(|(_, chunk)| chunk.live_tiles > 0) {
+            for (index, &tile) in chunk.tiles[phase].iter().enumerate() {
+                if tile != Tile(0) {
+                    let x = chunk_coord.0 * CHUNK_SIZE + index as i64 % CHUNK_SIZE;
+                    let y = chunk_coord.1 * CHUNK_SIZE + index as i64 / CHUNK_SIZE;
+                    tiles.push((TCoord(x, y), tile));
                 }
-                
             }
-            (next_active, next_gen)
         }
+        tiles.sort_unstable_by_key(|(coord, _)| (coord.1, coord.0));
+        tiles
+    }
 
-        fn p01_calc(&self, tile: Tile, right: Tile, down: Tile, downright: Tile) -> Tile {
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
 
-            let center_data = tile.0;
-            let down_data = (center_data << 8) + (down.0 >> 24);
-            let right_data = ((center_data << 2) & 0xcccccccc) + ((right.0 >> 2) & 0x33333333);
-            let downright_data = ((down_data << 2) & 0xcccccccc) + ((((right.0 << 8) + (downright.0 >> 24)) >> 2) & 0x33333333);
+    pub fn set_gc_policy(&mut self, policy: GcPolicy) {
+        self.gc_policy = policy;
+    }
 
-            Tile(
-                (self.rule_table.0[(center_data >> 16) as usize] << 26) +
-                (self.rule_table.0[(down_data >> 16) as usize] << 18) +
-                (self.rule_table.0[(center_data & 0xffff) as usize] << 10) +
-                (self.rule_table.0[(down_data & 0xffff) as usize] << 2) +
-                (self.rule_table.0[(right_data >> 16) as usize] << 24) +
-                (self.rule_table.0[(downright_data >> 16) as usize] << 16) +
-                (self.rule_table.0[(right_data & 0xffff) as usize] << 8) +
-                (self.rule_table.0[(downright_data & 0xffff) as usize])
-            )
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            chunks: self.chunks.len(),
+            empty_chunks: self.chunks.values().filter(|chunk| chunk.is_empty()).count(),
+            tiles: self.chunks.values().map(|chunk| chunk.live_tiles).sum(),
+            active_tiles: self.chunks.values()
+                .flat_map(|chunk| chunk.active.iter())
+                .map(|row| row.count_ones() as usize)
+                .sum(),
+            collections: self.collections,
         }
+    }
 
-        fn p10_calc(&self, tile: Tile, left: Tile, up: Tile, upleft: Tile) -> Tile {
+    fn should_collect(&self) -> bool {
+        match self.gc_policy {
+            GcPolicy::Never => false,
+            GcPolicy::Interval(generations) => generations > 0 && self.generation.is_multiple_of(generations),
+            GcPolicy::ZeroRatio(_) if !self.generation.is_multiple_of(GC_CHECK_INTERVAL) => false,
+            GcPolicy::ZeroRatio(ratio) => {
+                let chunks = self.chunks.len();
+                let empty_chunks = self.chunks.values().filter(|chunk| chunk.is_empty()).count();
+                chunks >= MIN_GC_CHUNKS && empty_chunks as f64 > ratio * chunks as f64
+            },
+        }
+    }
 
-            let center_data = tile.0;
-            let up_data = (center_data >> 8) + (up.0 << 24);
-            let left_data = ((center_data >> 2) & 0x33333333) + ((left.0 << 2) & 0xcccccccc) ;
-            let leftup_data = ((up_data >> 2) & 0x33333333) + ((((left.0 >> 8) + (upleft.0 << 24)) << 2) & 0xcccccccc);
+    pub fn gc(&mut self) {
+        self.chunks.retain(|_, chunk| !chunk.is_empty());
+        self.collections += 1;
+    }
 
-            Tile(
-                (self.rule_table.0[(leftup_data >> 16) as usize] << 26) +
-                (self.rule_table.0[(left_data >> 16) as usize] << 18) +
-                (self.rule_table.0[(leftup_data & 0xffff) as usize] << 10) +
-                (self.rule_table.0[(left_data & 0xffff) as usize] << 2) +
-                (self.rule_table.0[(up_data >> 16) as usize] << 24) +
-                (self.rule_table.0[(center_data >> 16) as usize] << 16) +
-                (self.rule_table.0[(up_data & 0xffff) as usize] << 8) +
-                (self.rule_table.0[(center_data & 0xffff) as usize])
-            )
+    pub fn step(&mut self) {
+        self.step_phase();
+        self.generation += 1;
+        if self.should_collect() {
+            self.gc();
         }
+    }
 
-        fn get_cell(&self, mut x: i64, mut y: i64) -> CellState {
-            // test both odd and even
-            // test (0, 0), (-1, 0), (-1,-1), (-1, 2)
-            // test 2^32, -2^32-1
+    // Calculates the active tiles of the other phase, chunk by chunk. Even
+    // generations read each p01 tile with its right, down and downright
+    // neighbours, odd ones each p10 tile with its left, up and upleft ones.
+    fn step_phase(&mut self) {
+        let phase = self.phase();
+        let direction = if phase == 0 { 1 } else { -1 };
+
+        // The new tiles are taken out of each chunk, so every chunk can be
+        // calculated at once while the current generation is only read.
+        let mut work: Vec<(TCoord, Box<[Tile]>)> = self.chunks.iter_mut()
+            .filter(|(_, chunk)| chunk.active.iter().any(|&row| row != 0))
+            .map(|(&coord, chunk)| (coord, std::mem::take(&mut chunk.tiles[1 - phase])))
+            .collect();
+        let results = self.calc_chunks(&mut work);
+
+        for ((coord, new_tiles), (changed, live_tiles)) in work.into_iter().zip(results) {
+            let chunk = self.chunks.get_mut(&coord).unwrap();
+            chunk.tiles[1 - phase] = new_tiles;
+            chunk.live_tiles = live_tiles;
+            self.activate_changed(coord, &changed, direction);
+        }
 
-            if self.generation % 2 == 1 {
-                x -= 1;
-                y -= 1;
+        for chunk in self.chunks.values_mut() {
+            for row in 0..CHUNK_SIZE as usize {
+                chunk.active[row] = chunk.next_active[row] | chunk.edited[row];
+                chunk.next_active[row] = 0;
+                chunk.edited[row] = 0;
             }
+        }
+    }
 
-            let coord_x = if x > 0 {
-                x / 4
-            } else {
-                (x / 4) + ((x % 4) - 3) / 4
-            };
+    // Chunks only read the current generation and write their own new
+    // tiles, so they are shared out between threads wherever there are any.
+    // The results are the same however the work is split. Small steps
+    // take less time than waking the thread pool does.
+    #[cfg(any(not(target_arch = "wasm32"), feature = "threads"))]
+    fn calc_chunks(&self, work: &mut [(TCoord, Box<[Tile]>)]) -> Vec<(ChunkBits, usize)> {
+        use rayon::prelude::*;
+        let active_tiles: u32 = work.iter()
+            .flat_map(|(coord, _)| self.chunks[coord].active.iter())
+            .map(|row| row.count_ones())
+            .sum();
+        if active_tiles < PARALLEL_MIN_TILES {
+            return work.iter_mut()
+                .map(|(coord, new_tiles)| self.calc_chunk(*coord, new_tiles))
+                .collect();
+        }
+        work.par_iter_mut()
+            .map(|(coord, new_tiles)| self.calc_chunk(*coord, new_tiles))
+            .collect()
+    }
 
-            let coord_y = if y > 0 {
-                y / 8
-            } else {
-                (y / 8) + ((y % 8) - 7) / 8
-            };
+    #[cfg(all(target_arch = "wasm32", not(feature = "threads")))]
+    fn calc_chunks(&self, work: &mut [(TCoord, Box<[Tile]>)]) -> Vec<(ChunkBits, usize)> {
+        work.iter_mut()
+            .map(|(coord, new_tiles)| self.calc_chunk(*coord, new_tiles))
+            .collect()
+    }
 
-            let coord = TCoord(coord_x, coord_y);
-            
-            let tile = if self.generation % 2 == 0 {
-                self.p01.get(&coord).cloned().unwrap_or(Tile(0))
-            } else {
-                self.p10.get(&coord).cloned().unwrap_or(Tile(0))
+    // Calculates the active tiles of a chunk into new_tiles, its tiles of
+    // the other phase, returning which of them changed and how many tiles
+    // the chunk has alive afterwards.
+    fn calc_chunk(&self, coord: TCoord, new_tiles: &mut [Tile]) -> (ChunkBits, usize) {
+        let phase = self.phase();
+        let direction = if phase == 0 { 1 } else { -1 };
+        let chunk = &self.chunks[&coord];
+        let across = self.chunks.get(&(coord + TCoord(direction, 0)));
+        let vertical = self.chunks.get(&(coord + TCoord(0, direction)));
+        let diagonal = self.chunks.get(&(coord + TCoord(direction, direction)));
+        let tile_at = |x: i64, y: i64| {
+            let inside_x = (0..CHUNK_SIZE).contains(&x);
+            let inside_y = (0..CHUNK_SIZE).contains(&y);
+            let source = match (inside_x, inside_y) {
+                (true, true) => Some(chunk),
+                (false, true) => across,
+                (true, false) => vertical,
+                (false, false) => diagonal,
             };
+            let index = y.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + x.rem_euclid(CHUNK_SIZE);
+            source.map_or(Tile(0), |source| source.tiles[phase][index as usize])
+        };
+
+        let mut changed: ChunkBits = [0; CHUNK_SIZE as usize];
+        let mut live_tiles = chunk.live_tiles;
+        for (y, &row) in chunk.active.iter().enumerate() {
+            let mut row = row;
+            while row != 0 {
+                let x = row.leading_zeros() as i64;
+                row &= !(1 << (63 - x));
+                let (x, y) = (x, y as i64);
+
+                let tile = tile_at(x, y);
+                let horizontal = tile_at(x + direction, y);
+                let vertical = tile_at(x, y + direction);
+                let diagonal = tile_at(x + direction, y + direction);
+                let new_tile = if phase == 0 {
+                    self.p01_calc(tile, horizontal, vertical, diagonal)
+                } else {
+                    self.p10_calc(tile, horizontal, vertical, diagonal)
+                };
 
+                // The new tile is in the other phase, so it has to be
+                // compared with what the tile held two generations ago, not
+                // with `tile`.
+                let index = (y * CHUNK_SIZE + x) as usize;
+                let old_tile = std::mem::replace(&mut new_tiles[index], new_tile);
+                if old_tile != new_tile {
+                    changed[y as usize] |= 1 << (63 - x);
+                    live_tiles = live_tiles + (new_tile != Tile(0)) as usize - (old_tile != Tile(0)) as usize;
+                }
+            }
+        }
+        (changed, live_tiles)
+    }
 
-            let t_x = if x >= 0 {
-                x % 4
-            } else {
-                ((x % 4) + 4) % 4
-            };
-
-            let t_y = if y >= 0 {
-                y % 8
+    // Marks the changed tiles of a chunk, and the tiles the next step
+    // calculates from them, in the given direction, to be calculated next.
+    fn activate_changed(&mut self, coord: TCoord, changed: &ChunkBits, direction: i64) {
+        let size = CHUNK_SIZE as usize;
+        let mut own: ChunkBits = [0; CHUNK_SIZE as usize];
+        let mut across: ChunkBits = [0; CHUNK_SIZE as usize];
+        let mut vertical = 0;
+        let mut diagonal = 0;
+
+        for (y, &row) in changed.iter().enumerate().filter(|(_, &row)| row != 0) {
+            let (spread, spill) = if direction > 0 {
+                (row | row >> 1, (row & 1) << 63)
             } else {
-                ((y % 8) + 8) % 8
+                (row | row << 1, row >> 63)
             };
+            own[y] |= spread;
+            across[y] |= spill;
+            match y as i64 + direction {
+                next_y if (0..CHUNK_SIZE).contains(&next_y) => {
+                    own[next_y as usize] |= spread;
+                    across[next_y as usize] |= spill;
+                },
+                _ => {
+                    vertical |= spread;
+                    diagonal |= spill;
+                },
+            }
+        }
 
-            if tile.0 >> (31 - 4*t_y - t_x) & 1 != 0 {
-                CellState::Alive
-            } else {
-                CellState::Dead
+        let edge = if direction > 0 { 0 } else { size - 1 };
+        let merge = |chunk: &mut Chunk, bits: &ChunkBits| {
+            for (row, &bits) in chunk.next_active.iter_mut().zip(bits) {
+                *row |= bits;
             }
+        };
+        merge(self.chunks.get_mut(&coord).unwrap(), &own);
+        if across.iter().any(|&row| row != 0) {
+            merge(self.chunks.entry(coord + TCoord(direction, 0)).or_insert_with(Chunk::new), &across);
+        }
+        if vertical != 0 {
+            self.chunks.entry(coord + TCoord(0, direction)).or_insert_with(Chunk::new).next_active[edge] |= vertical;
+        }
+        if diagonal != 0 {
+            self.chunks.entry(coord + TCoord(direction, direction)).or_insert_with(Chunk::new).next_active[edge] |= diagonal;
         }
+    }
 
-        fn set_cell(&mut self, mut x: i64, mut y: i64) {
-            // test both odd and even
-            // test (0, 0), (-1, 0), (-1,-1), (-1, 2)
-            // test 2^32, -2^32-1
+    // The next p10 tile at a coordinate, from the p01 tiles it overlaps:
+    // the 10x10 cells from its own top-left corner, one cell up and left of
+    // the new tile, take in all eight columns and rows of tile, the first two
+    // columns of right and the first two rows of down and downright.
+    fn p01_calc(&self, tile: Tile, right: Tile, down: Tile, downright: Tile) -> Tile {
+        let mut rows = [0u16; 10];
+        for (index, row) in rows.iter_mut().enumerate() {
+            let (left_tile, right_tile) = if index < 8 { (tile, right) } else { (down, downright) };
+            *row = tile_row(left_tile, index % 8) << 2 | tile_row(right_tile, index % 8) >> 6;
+        }
+        self.next_tile(&rows)
+    }
 
-            if self.generation % 2 == 1 {
-                x -= 1;
-                y -= 1;
-            }
+    // The next p01 tile at a coordinate, from the p10 tiles it overlaps: the
+    // last two columns of left and upleft, and the last two rows of up and
+    // upleft, around all of tile.
+    fn p10_calc(&self, tile: Tile, left: Tile, up: Tile, upleft: Tile) -> Tile {
+        let mut rows = [0u16; 10];
+        for (index, row) in rows.iter_mut().enumerate() {
+            let (left_tile, right_tile, tile_index) = if index < 2 { (upleft, up, index + 6) } else { (left, tile, index - 2) };
+            *row = (tile_row(left_tile, tile_index) & 0x3) << 8 | tile_row(right_tile, tile_index);
+        }
+        self.next_tile(&rows)
+    }
 
-            let coord_x = if x >= 0 {
-                x / 4
-            } else {
-                (x / 4) + ((x % 4) - 3) / 4
-            };
+    // Steps the middle 8x8 cells of ten rows of ten cells, each row with its
+    // leftmost cell in bit 9. All 64 cells are counted at once: each of the
+    // eight output rows gets a 16-bit lane of a u128, and the neighbour counts
+    // are added bit by bit with full adders.
+    fn next_tile(&self, rows: &[u16; 10]) -> Tile {
+        let mut above = 0u128;
+        let mut middle = 0u128;
+        let mut below = 0u128;
+        for lane in 0..8 {
+            let shift = 16 * (7 - lane);
+            above |= u128::from(rows[lane]) << shift;
+            middle |= u128::from(rows[lane + 1]) << shift;
+            below |= u128::from(rows[lane + 2]) << shift;
+        }
 
-            let coord_y = if y >= 0 {
-                y / 8
-            } else {
-                (y / 8) + ((y % 8) - 7) / 8
+        // Output column j is input column j + 1, so in each lane the left
+        // neighbours are shifted down by 2, the cells themselves by 1 and
+        // the right neighbours not at all.
+        let neighbours = [
+            above >> 2 & LANES, above >> 1 & LANES, above & LANES,
+            middle >> 2 & LANES, middle & LANES,
+            below >> 2 & LANES, below >> 1 & LANES, below & LANES,
+        ];
+        let alive = middle >> 1 & LANES;
+
+        let (ones_a, twos_a) = full_add(neighbours[0], neighbours[1], neighbours[2]);
+        let (ones_b, twos_b) = full_add(neighbours[3], neighbours[4], neighbours[5]);
+        let (ones_c, twos_c) = (neighbours[6] ^ neighbours[7], neighbours[6] & neighbours[7]);
+        let (ones, twos_d) = full_add(ones_a, ones_b, ones_c);
+        let (twos_e, fours_a) = full_add(twos_a, twos_b, twos_c);
+        let (twos, fours_b) = (twos_e ^ twos_d, twos_e & twos_d);
+        let (fours, eights) = (fours_a ^ fours_b, fours_a & fours_b);
+        let bits = [ones, twos, fours, eights];
+
+        let mut next = 0u128;
+        for count in 0..=8 {
+            let born = self.rule_masks.birth & 1 << count != 0;
+            let survives = self.rule_masks.survival & 1 << count != 0;
+            if !born && !survives {
+                continue;
+            }
+            let mut equal = LANES;
+            for (bit, &sum) in bits.iter().enumerate() {
+                equal &= if count & 1 << bit != 0 { sum } else { !sum };
+            }
+            next |= match (born, survives) {
+                (true, true) => equal,
+                (true, false) => equal & !alive,
+                _ => equal & alive,
             };
+        }
 
-            let coord = TCoord(coord_x, coord_y);
-            
-            let mut tile = if self.generation % 2 == 0 {
-                self.p01.get(&coord).cloned().unwrap_or(Tile(0))
-            } else {
-                self.p10.get(&coord).cloned().unwrap_or(Tile(0))
-            };
+        let mut tile = 0u64;
+        for lane in 0..8 {
+            let row = (next >> (16 * (7 - lane))) as u64 & 0xff;
+            tile |= row << (56 - 8 * lane);
+        }
+        Tile(tile)
+    }
 
+    fn perform_cell_action(&mut self, mut x: i64, mut y: i64, action: CellAction) -> CellState {
 
-            let t_x = if x >= 0 {
-                x % 4
-            } else {
-                ((x % 4) + 4) % 4
-            };
+        if self.generation % 2 == 1 {
+            x -= 1;
+            y -= 1;
+        }
 
-            let t_y = if y >= 0 {
-                y % 8
-            } else {
-                ((y % 8) + 8) % 8
-            };
-            
-            tile.0 |= 1 << (31 - 4*t_y - t_x);
-
-            if self.generation % 2 == 0 {
-                self.p01.insert(coord, tile);
-                self.active.insert(coord);
-                self.active.insert(coord - TCoord(1, 1));
-                self.active.insert(coord - TCoord(1, 0));
-                self.active.insert(coord - TCoord(0, 1));
-            } else {
-                self.p10.insert(coord, tile);
-                self.active.insert(coord);
-                self.active.insert(coord + TCoord(1, 1));
-                self.active.insert(coord + TCoord(1, 0));
-                self.active.insert(coord + TCoord(0, 1));
+        let coord = TCoord(x.div_euclid(8), y.div_euclid(8));
+
+        let mut tile = self.tile(coord);
+
+        let t_x = x.rem_euclid(8);
+        let t_y = y.rem_euclid(8);
+
+        match action {
+            CellAction::Birth => {
+                tile.0 |= 1 << (63 - 8*t_y - t_x);
+                self.put_tile(coord, tile);
+                self.mark_edited(coord);
+            },
+            CellAction::Death => {
+                tile.0 &= !(1 << (63 - 8*t_y - t_x));
+                self.put_tile(coord, tile);
+                self.mark_edited(coord);
+            },
+            CellAction::Toggle => {
+                tile.0 ^= 1 << (63 - 8*t_y - t_x);
+                self.put_tile(coord, tile);
+                self.mark_edited(coord);
             }
+            CellAction::Noop => {}
+        };
+        if tile.0 >> (63 - 8*t_y - t_x) & 1 != 0 {
+            CellState::Alive
+        } else {
+            CellState::Dead
         }
-    
-        pub fn render(&self, gl: &mut GL, canvas: &mut HtmlCanvasElement, 
-            program: &WebGlProgram, position_attribute_location: u32, position_buffer: &WebGlBuffer, resolution_uniform_location: &WebGlUniformLocation) {
-            self.resize_gl(gl, canvas);
-            gl.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
-            
-            gl.clear_color(0_f32, 0_f32, 0_f32, 0_f32);
-            gl.clear(GL::COLOR_BUFFER_BIT);
+    }
 
-            gl.use_program(Some(program));
+    fn get_cell(&mut self, x: i64, y: i64) -> CellState {
+        self.perform_cell_action(x, y, CellAction::Noop)
+    }
 
-              // Turn on the attribute
-            gl.enable_vertex_attrib_array(position_attribute_location);
+    pub fn set_cell(&mut self, x: i64, y: i64) {
+        self.perform_cell_action(x, y, CellAction::Birth);
+    }
 
-            // Bind the position buffer.
-            gl.bind_buffer(GL::ARRAY_BUFFER, Some(position_buffer));
+    pub fn kill_cell(&mut self, x: i64, y: i64) {
+        self.perform_cell_action(x, y, CellAction::Death);
+    }
 
-            // Tell the attribute how to get data out of positionBuffer (ARRAY_BUFFER)
-            let size = 2;          // 2 components per iteration
-            let gl_type = GL::FLOAT;   // the data is 32bit floats
-            let normalize = false; // don't normalize the data
-            let stride = 0;        // 0 = move forward size * sizeof(type) each iteration to get the next position
-            let offset = 0;        // start at the beginning of the buffer
-            gl.vertex_attrib_pointer_with_i32(
-                position_attribute_location, size, gl_type, normalize, stride, offset);
+    pub fn toggle_cell(&mut self, x: i64, y: i64) {
+        self.perform_cell_action(x, y, CellAction::Toggle);
+    }
 
-            // set the resolution
-            gl.uniform2f(Some(resolution_uniform_location), canvas.width() as f32, canvas.height() as f32);
+    pub fn set_pattern(&mut self, x: i64, y: i64, pattern: &Pattern) {
+        self.paste_pattern(x, y, pattern, PasteMode::Copy);
+    }
 
-            // draw 50 random rectangles in random colors
+    pub fn paste_pattern(&mut self, x: i64, y: i64, pattern: &Pattern, mode: PasteMode) {
+        match mode {
+            PasteMode::Copy => {
+                self.clear_region(x, y, pattern.width, pattern.height);
+                for &(cell_x, cell_y) in &pattern.cells {
+                    self.set_cell(x + cell_x, y + cell_y);
+                }
+            },
+            PasteMode::Or => {
+                for &(cell_x, cell_y) in &pattern.cells {
+                    self.set_cell(x + cell_x, y + cell_y);
+                }
+            },
+            PasteMode::Xor => {
+                for &(cell_x, cell_y) in &pattern.cells {
+                    self.toggle_cell(x + cell_x, y + cell_y);
+                }
+            },
+            PasteMode::And => {
+                let keep: FnvHashSet<(i64, i64)> = pattern.cells.iter().cloned().collect();
+                let existing = self.get_pattern(x, y, pattern.width, pattern.height);
+                for (cell_x, cell_y) in existing.cells {
+                    if !keep.contains(&(cell_x, cell_y)) {
+                        self.kill_cell(x + cell_x, y + cell_y);
+                    }
+                }
+            },
+        }
+    }
 
-            //unimplemented!("not implemented");
+    pub fn get_pattern(&mut self, x: i64, y: i64, width: i64, height: i64) -> Pattern {
+        let tiles: usize = self.chunks.values().map(|chunk| chunk.live_tiles).sum();
+
+        // Small regions are cheaper to read cell by cell than to scan every tile.
+        let cells = if width * height <= tiles as i64 * 64 {
+            let mut cells = Vec::new();
+            for cell_y in 0..height {
+                for cell_x in 0..width {
+                    if self.get_cell(x + cell_x, y + cell_y) == CellState::Alive {
+                        cells.push((cell_x, cell_y));
+                    }
+                }
+            }
+            cells
+        } else {
+            // Tiles come out in map order, so the cells are sorted to match the
+            // row by row order of the branch above.
+            let mut cells: Vec<(i64, i64)> = self.live_cells().into_iter()
+                .filter(|&(cell_x, cell_y)| cell_x >= x && cell_x < x + width && cell_y >= y && cell_y < y + height)
+                .map(|(cell_x, cell_y)| (cell_x - x, cell_y - y))
+                .collect();
+            cells.sort_unstable_by_key(|&(cell_x, cell_y)| (cell_y, cell_x));
+            cells
+        };
+
+        Pattern { width, height, cells }
+    }
 
+    pub fn clear_region(&mut self, x: i64, y: i64, width: i64, height: i64) {
+        for cell_y in y..y + height {
+            for cell_x in x..x + width {
+                self.kill_cell(cell_x, cell_y);
+            }
         }
+    }
+
+    // The smallest (x, y, width, height) holding every live cell.
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let cells = self.live_cells();
+        let min_x = cells.iter().map(|&(x, _)| x).min()?;
+        let min_y = cells.iter().map(|&(_, y)| y).min()?;
+        let max_x = cells.iter().map(|&(x, _)| x).max()?;
+        let max_y = cells.iter().map(|&(_, y)| y).max()?;
+        Some((min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+    }
+
+    pub fn live_cells(&self) -> Vec<(i64, i64)> {
+        TileView { offset: self.phase() as i64, tiles: self.tiles() }.cells()
+    }
 
-        fn resize_gl(&self, gl: &mut GL, canvas: &mut HtmlCanvasElement) {
-            // Lookup the size the browser is displaying the canvas.
-            let display_width = canvas.client_width() as u32;
-            let display_height = canvas.client_height() as u32;
-            
-            // Check if the canvas is not the same size.
-            if canvas.width() != display_width || canvas.height() != display_height {
-                // Make the canvas the same size
-                canvas.set_width(display_width);
-                canvas.set_height(display_height);
+    // The tiles holding any cell of the region, which may also hold cells
+    // just outside it.
+    pub fn visible_tiles(&self, x: i64, y: i64, width: i64, height: i64) -> TileView {
+        let offset = self.phase() as i64;
+        let tile_range = |start: i64, length: i64| ((start - offset).div_euclid(8), (start + length - 1 - offset).div_euclid(8));
+        let (left, right) = tile_range(x, width.max(1));
+        let (top, bottom) = tile_range(y, height.max(1));
+        let chunk_range = |first: i64, last: i64| (first.div_euclid(CHUNK_SIZE), last.div_euclid(CHUNK_SIZE));
+        let (chunk_left, chunk_right) = chunk_range(left, right);
+        let (chunk_top, chunk_bottom) = chunk_range(top, bottom);
+
+        // Zoomed far out, the region can cover more chunk coordinates than
+        // there are chunks.
+        let covered = (chunk_right - chunk_left + 1).saturating_mul(chunk_bottom - chunk_top + 1);
+        let chunks: Vec<(TCoord, &Chunk)> = if covered as usize <= self.chunks.len() {
+            (chunk_top..=chunk_bottom)
+                .flat_map(|chunk_y| (chunk_left..=chunk_right).map(move |chunk_x| TCoord(chunk_x, chunk_y)))
+                .filter_map(|coord| self.chunks.get(&coord).map(|chunk| (coord, chunk)))
+                .collect()
+        } else {
+            self.chunks.iter()
+                .filter(|(coord, _)| coord.0 >= chunk_left && coord.0 <= chunk_right && coord.1 >= chunk_top && coord.1 <= chunk_bottom)
+                .map(|(&coord, chunk)| (coord, chunk))
+                .collect()
+        };
+
+        let phase = self.phase();
+        let mut tiles = Vec::new();
+        for (coord, chunk) in chunks.into_iter().filter(|(_, chunk)| chunk.live_tiles > 0) {
+            let (origin_x, origin_y) = (coord.0 * CHUNK_SIZE, coord.1 * CHUNK_SIZE);
+            for tile_y in top.max(origin_y)..=bottom.min(origin_y + CHUNK_SIZE - 1) {
+                for tile_x in left.max(origin_x)..=right.min(origin_x + CHUNK_SIZE - 1) {
+                    let tile = chunk.tiles[phase][((tile_y - origin_y) * CHUNK_SIZE + tile_x - origin_x) as usize];
+                    if tile != Tile(0) {
+                        tiles.push((TCoord(tile_x, tile_y), tile));
+                    }
+                }
             }
         }
+        TileView { offset, tiles }
+    }
 
-        fn move_view(&mut self, x: i64, y: i64) {
-            self.x += x;
-            self.y += y;
-        }
+    pub fn population(&self) -> u64 {
+        let phase = self.phase();
+        self.chunks.values()
+            .filter(|chunk| chunk.live_tiles > 0)
+            .flat_map(|chunk| chunk.tiles[phase].iter())
+            .map(|tile| u64::from(tile.0.count_ones()))
+            .sum()
+    }
+
+    // Steps until the pattern dies out or repeats a shape seen before,
+    // giving up after max_generations.
+    pub fn find_period(&mut self, max_generations: u64) -> Option<Periodicity> {
+        let mut seen: FnvHashMap<Vec<(i64, i64)>, (u64, i64, i64)> = FnvHashMap::default();
+        let end = self.generation + max_generations;
 
-        // use include_str! for popular patterns
+        loop {
+            let cells = self.live_cells();
+            if cells.is_empty() {
+                return Some(Periodicity::DiesOut { generation: self.generation });
+            }
+            let x = cells.iter().map(|cell| cell.0).min().unwrap();
+            let y = cells.iter().map(|cell| cell.1).min().unwrap();
+            let shape = Pattern::from_cells(cells).cells;
+
+            if let Some(&(start, start_x, start_y)) = seen.get(&shape) {
+                return Some(Periodicity::Periodic {
+                    start,
+                    period: self.generation - start,
+                    dx: x - start_x,
+                    dy: y - start_y,
+                });
+            }
+            if self.generation >= end {
+                return None;
+            }
+            seen.insert(shape, (self.generation, x, y));
+            self.step();
+        }
     }
 
-    impl Default for Universe {
+    // use include_str! for popular patterns
+}
+
+// The cells in one row of a tile, leftmost in bit 7.
+fn tile_row(tile: Tile, row: usize) -> u16 {
+    (tile.0 >> (56 - 8 * row) & 0xff) as u16
+}
+
+// The sum and carry of three one-bit numbers in every bit position.
+fn full_add(a: u128, b: u128, c: u128) -> (u128, u128) {
+    let partial = a ^ b;
+    (partial ^ c, a & b | partial & c)
+}
+
+fn counts_mask(counts: &[u32]) -> u16 {
+    counts.iter().fold(0u16, |mask, &count| mask | 1 << count)
+}
+
+impl TileView {
+
+    pub fn cells(&self) -> Vec<(i64, i64)> {
+        let mut cells = Vec::new();
+        for (coord, tile) in &self.tiles {
+            let mut bits = tile.0;
+            while bits != 0 {
+                let bit = i64::from(bits.leading_zeros());
+                bits &= !(1 << (63 - bit));
+                cells.push((coord.0 * 8 + bit % 8 + self.offset, coord.1 * 8 + bit / 8 + self.offset));
+            }
+        }
+        cells
+    }
 
-        fn default() -> Universe {
-            let b = vec!(3);
-            let s = vec!(2, 3);
+    // The tiles in a width x height block of tile coordinates from (x, y),
+    // packed for a texture: eight bytes a tile, row-major. Each tile is its
+    // u64 in big-endian order, so a byte per row of cells, top row first,
+    // with the leftmost cell in the high bit.
+    pub fn pack(&self, x: i64, y: i64, width: i64, height: i64) -> Vec<u8> {
+        let mut bytes = vec![0; (width * height * 8) as usize];
+        for (coord, tile) in &self.tiles {
+            let (column, row) = (coord.0 - x, coord.1 - y);
+            if (0..width).contains(&column) && (0..height).contains(&row) {
+                let start = ((row * width + column) * 8) as usize;
+                bytes[start..start + 8].copy_from_slice(&tile.0.to_be_bytes());
+            }
+        }
+        bytes
+    }
 
-            Universe::new(b, s)
+    // The density of the view's cells over the blocks covering a region of
+    // cells. Blocks are counted a row of up to eight cells at a time.
+    pub fn density(&self, x: i64, y: i64, width: i64, height: i64, block: i64) -> DensityView {
+        let left = (x - self.offset).div_euclid(block);
+        let top = (y - self.offset).div_euclid(block);
+        let grid_width = (x + width - 1 - self.offset).div_euclid(block) + 1 - left;
+        let grid_height = (y + height - 1 - self.offset).div_euclid(block) + 1 - top;
+
+        let mut counts = vec![0u64; (grid_width * grid_height) as usize];
+        let span = block.min(8);
+        let mask = (1u64 << span) - 1;
+        for (coord, tile) in &self.tiles {
+            for row in 0..8 {
+                let bits = (tile.0 >> (56 - 8 * row)) & 0xff;
+                if bits == 0 {
+                    continue;
+                }
+                let grid_y = (coord.1 * 8 + row).div_euclid(block) - top;
+                if !(0..grid_height).contains(&grid_y) {
+                    continue;
+                }
+                for start in (0..8).step_by(span as usize) {
+                    let count = u64::from(((bits >> (8 - start - span)) & mask).count_ones());
+                    let grid_x = (coord.0 * 8 + start).div_euclid(block) - left;
+                    if count > 0 && (0..grid_width).contains(&grid_x) {
+                        counts[(grid_y * grid_width + grid_x) as usize] += count;
+                    }
+                }
+            }
         }
+
+        let cells = (block as u64).saturating_mul(block as u64);
+        let levels = counts.into_iter()
+            .map(|count| match count {
+                0 => 0,
+                count => (count.saturating_mul(255) / cells).max(1) as u8,
+            })
+            .collect();
+        DensityView { offset: self.offset, block, x: left, y: top, width: grid_width, height: grid_height, levels }
+    }
+}
+
+impl Default for Universe {
+
+    fn default() -> Universe {
+        let b = vec!(3);
+        let s = vec!(2, 3);
+
+        Universe::new(b, s)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::reference::soup;
+
+    #[test]
+    fn test_p01_calc() {
+        let universe = Universe::default();
+
+        // A block on the corner of all four tiles.
+        let tile = Tile(1);
+        let right = Tile(1 << 7);
+        let down = Tile(1 << 56);
+        let downright = Tile(1 << 63);
+        assert_eq!(Tile(0x303), universe.p01_calc(tile, right, down, downright));
+
+        // A blinker across the bottom of tile and right turns upright,
+        // reaching into what is the bottom row of the new tile.
+        let tile = Tile(0b11);
+        let right = Tile(1 << 7);
+        let expected = Tile(1 << 17 | 1 << 9 | 1 << 1);
+        assert_eq!(expected, universe.p01_calc(tile, right, Tile(0), Tile(0)));
     }
 
+    #[test]
+    fn test_p10_calc() {
+        let universe = Universe::default();
 
-    #[cfg(test)]
-    mod test {
+        // A block on the corner of all four tiles, and an upright blinker in
+        // the middle of tile.
+        let tile = Tile(1 << 63 | 1 << 44 | 1 << 36 | 1 << 28);
+        let left = Tile(1 << 56);
+        let up = Tile(1 << 7);
+        let upleft = Tile(1);
 
-        use super::*;
+        let expected = Tile(1 << 63 | 1 << 62 | 1 << 55 | 1 << 54 | 1 << 28 | 1 << 27 | 1 << 26);
+        assert_eq!(expected, universe.p10_calc(tile, left, up, upleft));
+    }
 
-        #[test]
-        fn test_p01_calc() {
-            let universe = Universe::default();
-            let tile = Tile(0xc800_2220);
-            let right = Tile(0x8880_0008);
-            let down = Tile(0xd200_0000);
-            let downright = Tile(0x8000_0000);
+    #[test]
+    fn test_step() {
+        let mut universe = Universe::default();
+        universe.set_cell(1, 1);
+        universe.set_cell(2, 1);
+        universe.set_cell(3, 1);
+        universe.step();
+
+        assert_eq!(CellState::Dead, universe.get_cell(1, 1));
+        assert_eq!(CellState::Alive, universe.get_cell(2, 1));
+        assert_eq!(CellState::Dead, universe.get_cell(3, 1));
+        assert_eq!(CellState::Alive, universe.get_cell(2, 2));
+        assert_eq!(CellState::Alive, universe.get_cell(2, 0));
+
+        universe.step();
+        assert_eq!(CellState::Alive, universe.get_cell(1, 1));
+        assert_eq!(CellState::Alive, universe.get_cell(2, 1));
+        assert_eq!(CellState::Alive, universe.get_cell(3, 1));
 
-            let expected = Tile(0xb000_e2df);
-            let result = universe.p01_calc(tile, right, down, downright);
+    }
+
+    #[test]
+    fn test_many_steps() {
+        let mut universe = Universe::default();
+        universe.set_cell(0, 0);
+        universe.set_cell(1, 0);
+        universe.set_cell(2, 0);
+        universe.set_cell(2, -1);
+        universe.set_cell(1, -2);
 
-            assert_eq!(expected, result);
+        for _i in 0..1000 {
+            universe.step();
         }
 
-        #[test]
-        fn test_p10_calc() {
-            let universe = Universe::default();
-            let tile = Tile(0x0044_4013);
-            let left = Tile(0x3100_0013);
-            let up = Tile(0x18);
-            let upleft = Tile(0x81);
+        let expected = 5;
+        assert_eq!(expected, universe.population());
+    }
+
+    #[test]
+    fn test_from_rle() {
+        let pattern = Pattern::from_rle("#N Glider\nx = 3, y = 3, rule = B3/S23\nbob$2bo$3o!").unwrap();
 
-            let expected = Tile(0xc0c47009);
-            let result = universe.p10_calc(tile, left, up, upleft);
+        assert_eq!(3, pattern.width);
+        assert_eq!(3, pattern.height);
+        assert_eq!(vec!((1, 0), (2, 1), (0, 2), (1, 2), (2, 2)), pattern.cells);
+    }
+
+    #[test]
+    fn test_from_rle_error() {
+        assert!(Pattern::from_rle("2o?o!").is_err());
+        assert!(Pattern::from_rle("99999999999999999999o!").is_err());
+        assert!(Pattern::from_rle("1073741824o!").is_err());
+        assert!(Pattern::from_rle("1073741823b$1073741823bo!").is_ok());
+        assert!(Pattern::from_rle("1073741823b1073741823bo!").is_err());
+        assert!(Pattern::from_rle("16777217o!").is_err());
+    }
+
+    #[test]
+    fn test_from_plaintext() {
+        let pattern = Pattern::from_plaintext("!Name: Glider\n.O\n..O\nOOO\n").unwrap();
+
+        assert_eq!(Pattern::from_rle("bo$2bo$3o!").unwrap(), pattern);
+        assert!(Pattern::from_plaintext("!Name: Bad\n.O.x\n").is_err());
+    }
+
+    #[test]
+    fn test_from_macrocell() {
+        let macrocell = "[M2] (golly 3.3)\n#R B3/S23\n.*$..*$***$\n4 1 0 0 1\n";
+        let mut pattern = Pattern::from_macrocell(macrocell).unwrap();
+        pattern.cells.sort();
+
+        assert_eq!(11, pattern.width);
+        assert_eq!(11, pattern.height);
+        assert_eq!(vec!((0, 2), (1, 0), (1, 2), (2, 1), (2, 2), (8, 10), (9, 8), (9, 10), (10, 9), (10, 10)), pattern.cells);
+
+        assert!(Pattern::from_macrocell(".*$\n5 1 0 0 0\n").is_err());
+        assert!(Pattern::from_macrocell("4 2 0 0 0\n").is_err());
+        assert!(Pattern::from_macrocell("*********$\n").is_err());
+    }
 
-            assert_eq!(expected, result);
+    #[test]
+    fn test_from_macrocell_limits() {
+        // Each level quadruples the cells of a full node.
+        let mut macrocell = "********$********$********$********$********$********$********$********$\n".to_string();
+        for level in 4..63 {
+            let child = level - 3;
+            macrocell += &format!("{} {} {} {} {}\n", level, child, child, child, child);
         }
+        assert!(Pattern::from_macrocell(&macrocell).is_err());
+
+        // Empty leaves and empty branches shared by every node above them
+        // expand to nothing, without visiting each copy.
+        for first in &["$\n", "4 0 0 0 0\n"] {
+            let mut macrocell = format!("[M2] (golly 3.3)\n{}", first);
+            let start = if first.starts_with('$') { 4 } else { 5 };
+            for level in start..63 {
+                let child = level - start + 1;
+                macrocell += &format!("{} {} {} {} {}\n", level, child, child, child, child);
+            }
+            assert_eq!(Ok(Pattern::from_cells(Vec::new())), Pattern::from_macrocell(&macrocell));
+        }
+
+        // Few enough cells, but each at the end of a long path of its own.
+        let mut macrocell = String::from("*$\n");
+        for level in 4..63 {
+            let child = level - 3;
+            if level < 52 {
+                macrocell += &format!("{} {} 0 0 0\n", level, child);
+            } else {
+                macrocell += &format!("{} {} {} {} {}\n", level, child, child, child, child);
+            }
+        }
+        assert!(Pattern::from_macrocell(&macrocell).is_err());
+
+        // Two cells at opposite corners of a level 40 node are too far apart.
+        let mut macrocell = String::from("*$\n");
+        let (mut nw, mut se) = (1, 1);
+        for level in 4..40 {
+            macrocell += &format!("{} {} 0 0 0\n{} 0 0 0 {}\n", level, nw, level, se);
+            nw = 2 * level - 6;
+            se = nw + 1;
+        }
+        macrocell += &format!("40 {} 0 0 {}\n", nw, se);
+        assert!(Pattern::from_macrocell(&macrocell).is_err());
+    }
+
+    #[test]
+    fn test_from_file() {
+        assert_eq!(Pattern::from_rle("3o!"), Pattern::from_file("blinker.RLE", "3o!"));
+        assert_eq!(Pattern::from_plaintext("OOO"), Pattern::from_file("blinker.cells", "OOO"));
+        assert!(Pattern::from_file("blinker.txt", "OOO").is_err());
+    }
+
+    #[test]
+    fn test_from_apgcode() {
+        assert_eq!(Pattern::from_rle("2o$2o!"), Pattern::from_apgcode("xs4_33"));
+        assert_eq!(Pattern::from_rle("3o$2bo$bo!"), Pattern::from_apgcode("xq4_153"));
+        assert_eq!(Pattern::from_rle("o2bo5$o!"), Pattern::from_apgcode("xs3_1w1z1"));
+        assert_eq!(Pattern::from_rle("o5bo!"), Pattern::from_apgcode("xs2_1y11"));
+        assert!(Pattern::from_apgcode("yl144_1_16_afb5f3db909e60548f086e22ee3353ac").is_err());
+        assert!(Pattern::from_apgcode("xs4_3?").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let glider = Pattern::from_rle("bo$2bo$3o!");
+        assert_eq!(glider, Pattern::parse("x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n"));
+        assert_eq!(glider, Pattern::parse("!Name: Glider\n.O\n..O\nOOO\n"));
+        assert_eq!(Pattern::from_rle("3o$2bo$bo!"), Pattern::parse(" xq4_153\n"));
+        assert!(Pattern::parse("not a pattern").is_err());
+    }
+
+    #[test]
+    fn test_to_rle() {
+        let pattern = Pattern::from_rle("2bo$$3o3b2o$b!").unwrap();
+        let rle = pattern.to_rle("B3/S23");
+        assert_eq!("x = 8, y = 3, rule = B3/S23\n2bo2$3o3b2o!\n", rle);
+        assert_eq!(Ok(pattern), Pattern::from_rle(&rle));
+
+        let wide = Pattern::from_cells((0..100).map(|x| (2 * x, 0)).collect());
+        let rle = wide.to_rle("B3/S23");
+        assert!(rle.lines().all(|line| line.len() <= 70));
+        assert_eq!(Ok(wide), Pattern::from_rle(&rle));
+    }
+
+    #[test]
+    fn test_transform() {
+        let pattern = Pattern::from_rle("3o$o!").unwrap();
+        let mut rotated = pattern.transform(Transform::Rotate90);
+        rotated.cells.sort();
+
+        assert_eq!(2, rotated.width);
+        assert_eq!(3, rotated.height);
+        assert_eq!(vec!((0, 0), (1, 0), (1, 1), (1, 2)), rotated.cells);
+
+        let mut flipped = pattern.transform(Transform::FlipHorizontal);
+        flipped.cells.sort();
+        assert_eq!(vec!((0, 0), (1, 0), (2, 0), (2, 1)), flipped.cells);
+
+        let mut transposed = pattern.transform(Transform::Transpose);
+        transposed.cells.sort();
+        assert_eq!(vec!((0, 0), (0, 1), (0, 2), (1, 0)), transposed.cells);
+
+        let half_turn = pattern.transform(Transform::Rotate90).transform(Transform::Rotate90);
+        assert_eq!(pattern.transform(Transform::Rotate180), half_turn);
+        assert_eq!(pattern, half_turn.transform(Transform::Rotate180));
+        assert_eq!(pattern, pattern.transform(Transform::Rotate270).transform(Transform::Rotate90));
+        assert_eq!(pattern, pattern.transform(Transform::FlipVertical).transform(Transform::FlipVertical));
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(vec!((0, 0)), line((0, 0), (0, 0)));
+        assert_eq!(vec!((0, 0), (1, 0), (2, 1), (3, 1)), line((0, 0), (3, 1)));
+        assert_eq!(vec!((3, 1), (2, 1), (1, 0), (0, 0)), line((3, 1), (0, 0)));
+        assert_eq!(vec!((-1, -1), (-1, 0), (-1, 1)), line((-1, -1), (-1, 1)));
+    }
+
+    #[test]
+    fn test_rectangle() {
+        assert_eq!(9, rectangle((2, 2), (0, 0), true).len());
+        assert_eq!(8, rectangle((0, 0), (2, 2), false).len());
+        assert!(!rectangle((0, 0), (2, 2), false).contains(&(1, 1)));
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let mut circle = ellipse((0, 0), (4, 4), false);
+        circle.sort();
+        assert_eq!(vec!(
+            (0, 1), (0, 2), (0, 3), (1, 0), (1, 4), (2, 0),
+            (2, 4), (3, 0), (3, 4), (4, 1), (4, 2), (4, 3)), circle);
+
+        let disc = ellipse((4, 4), (0, 0), true);
+        assert_eq!(21, disc.len());
+        assert_eq!(vec!((0, 0)), ellipse((0, 0), (0, 0), true));
+    }
 
-        #[test]
-        fn test_step() {
+    #[test]
+    fn test_paste_pattern() {
+        let pattern = Pattern::from_rle("2ob!").unwrap();
+        let paste = |mode| {
             let mut universe = Universe::default();
-            universe.set_cell(1, 1);
-            universe.set_cell(2, 1);
-            universe.set_cell(3, 1);
+            universe.set_cell(1, 0);
+            universe.set_cell(2, 0);
+            universe.paste_pattern(0, 0, &pattern, mode);
+            let mut cells = universe.live_cells();
+            cells.sort();
+            cells
+        };
+
+        assert_eq!(vec!((0, 0), (1, 0)), paste(PasteMode::Copy));
+        assert_eq!(vec!((0, 0), (1, 0), (2, 0)), paste(PasteMode::Or));
+        assert_eq!(vec!((1, 0)), paste(PasteMode::And));
+        assert_eq!(vec!((0, 0), (2, 0)), paste(PasteMode::Xor));
+    }
+
+    #[test]
+    fn test_set_pattern() {
+        let mut universe = Universe::default();
+        universe.set_cell(-2, -4);
+        universe.set_cell(-2, -3);
+        universe.set_pattern(-3, -4, &Pattern::from_rle("obo!").unwrap());
+
+        let mut cells = universe.live_cells();
+        cells.sort();
+        assert_eq!(vec!((-3, -4), (-2, -3), (-1, -4)), cells);
+
+        let mut pattern = universe.get_pattern(-2, -4, 2, 2);
+        pattern.cells.sort();
+        assert_eq!(vec!((0, 1), (1, 0)), pattern.cells);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let mut universe = Universe::default();
+        assert_eq!(None, universe.bounding_box());
+
+        universe.set_pattern(-5, 3, &Pattern::from_rle("bo$2bo$3o!").unwrap());
+        universe.step();
+        let (x, y, width, height) = universe.bounding_box().unwrap();
+        assert_eq!((-5, 4, 3, 3), (x, y, width, height));
+
+        // Restoring the cells at the same generation continues identically.
+        let pattern = universe.get_pattern(x, y, width, height);
+        let mut restored = Universe { generation: universe.generation, ..Universe::default() };
+        restored.set_pattern(x, y, &Pattern::from_rle(&pattern.to_rle("B3/S23")).unwrap());
+        for _ in 0..9 {
             universe.step();
+            restored.step();
+        }
+        let mut expected = universe.live_cells();
+        let mut cells = restored.live_cells();
+        expected.sort();
+        cells.sort();
+        assert_eq!(expected, cells);
+    }
 
-            assert_eq!(CellState::Dead, universe.get_cell(1, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 1));
-            assert_eq!(CellState::Dead, universe.get_cell(3, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 2));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 0));
+    #[test]
+    fn test_snapshot() {
+        let mut universe = Universe::new(vec!(3, 6), vec!(2, 3));
+        assert_eq!("B36/S23", universe.rule());
+        universe.set_pattern(-40, -3, &Pattern::from_rle("3o$o$bo!").unwrap());
+        universe.set_pattern(1 << 33, -(1 << 33), &Pattern::from_rle("b2o$2o$bo!").unwrap());
+
+        // Compares the loaded copy with the universe it was saved from, at both
+        // parities and again after stepping them side by side.
+        fn assert_same(universe: &Universe, loaded: &Universe) {
+            let mut expected = universe.live_cells();
+            let mut cells = loaded.live_cells();
+            expected.sort();
+            cells.sort();
+            assert_eq!(expected, cells);
+            assert_eq!(universe.generation, loaded.generation);
+            assert_eq!(universe.rule(), loaded.rule());
+        }
 
+        for _ in 0..2 {
             universe.step();
-            assert_eq!(CellState::Alive, universe.get_cell(1, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(2, 1));
-            assert_eq!(CellState::Alive, universe.get_cell(3, 1));
+            let snapshot = universe.save();
+            let mut loaded = Universe::load(&snapshot).unwrap();
+            assert_same(&universe, &loaded);
+            assert_eq!(snapshot, loaded.save());
+
+            let mut original = universe.clone();
+            for _ in 0..5 {
+                original.step();
+                loaded.step();
+            }
+            assert_same(&original, &loaded);
+        }
 
+        let snapshot = universe.save();
+        assert!(Universe::load(b"CONK").is_err());
+        assert!(Universe::load(&snapshot[1..]).is_err());
+        let mut corrupt = snapshot.clone();
+        corrupt[5] ^= 1;
+        assert!(Universe::load(&corrupt).is_err());
+        let mut corrupt = snapshot;
+        corrupt[4] = 9;
+        assert!(Universe::load(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_version_1() {
+        // A blinker in the 4x8 tile left of the origin, at either parity.
+        for &generation in &[0u64, 1] {
+            let mut body = Vec::new();
+            body.extend_from_slice(&generation.to_le_bytes());
+            body.extend_from_slice(&(1u16 << 3).to_le_bytes());
+            body.extend_from_slice(&(1u16 << 2 | 1 << 3).to_le_bytes());
+            body.push(generation as u8);
+            body.extend_from_slice(&1u64.to_le_bytes());
+            body.extend_from_slice(&(-1i64).to_le_bytes());
+            body.extend_from_slice(&0i64.to_le_bytes());
+            body.extend_from_slice(&(1u32 << 30 | 1 << 29 | 1 << 28).to_le_bytes());
+
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&body).unwrap();
+            let mut snapshot = b"CONK\x01".to_vec();
+            snapshot.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+            snapshot.extend_from_slice(&encoder.finish().unwrap());
+
+            let universe = Universe::load(&snapshot).unwrap();
+            let offset = generation as i64;
+            let mut cells = universe.live_cells();
+            cells.sort();
+            assert_eq!(vec!((offset - 3, offset), (offset - 2, offset), (offset - 1, offset)), cells);
+            assert_eq!(SNAPSHOT_VERSION, universe.save()[4]);
         }
+    }
+
+    #[test]
+    fn test_to_plaintext() {
+        let pattern = Pattern::from_rle("bo$2bo$3o!").unwrap();
+        assert_eq!(".O.\n..O\nOOO\n", pattern.to_plaintext());
+        assert_eq!(Ok(pattern.clone()), Pattern::from_plaintext(&pattern.to_plaintext()));
+
+        // Cells in any order, with empty rows between them.
+        let pattern = Pattern { width: 3, height: 4, cells: vec!((2, 3), (0, 0), (1, 3), (2, 0)) };
+        assert_eq!("O.O\n...\n...\n.OO\n", pattern.to_plaintext());
 
-        #[test]
-        fn test_many_steps() {
+        // A region read from tiles across several chunks.
+        let mut universe = Universe::default();
+        for &(x, y) in &[(500, 3), (-300, 3), (7, -200), (-1, 3)] {
+            universe.set_cell(x, y);
+        }
+        let pattern = universe.get_pattern(-300, -200, 801, 204);
+        assert_eq!(vec!((307, 0), (0, 203), (299, 203), (800, 203)), pattern.cells);
+        assert_eq!(4, pattern.to_plaintext().matches('O').count());
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(Some((vec!(3), vec!(2, 3))), parse_rule("B3/S23"));
+        assert_eq!(Some((vec!(3), vec!(2, 3))), parse_rule("s32/b3"));
+        assert_eq!(Some((vec!(3, 6), vec!(2, 3))), parse_rule("23/36"));
+        assert_eq!(Some((vec!(2), vec!())), parse_rule("B2/S"));
+        assert_eq!(None, parse_rule("B9/S23"));
+        assert_eq!("B36/S23", format_rule(&[6, 3], &[3, 2, 3]));
+        assert_eq!(Some((vec!(3), vec!(0, 1, 2, 3, 4, 5, 6, 7, 8))), rle_rule("#N Life without death\nx = 1, y = 1, rule = b3/s012345678\no!"));
+        assert_eq!(None, rle_rule("x = 1, y = 1\no!"));
+    }
+
+    #[test]
+    fn test_find_period() {
+        let mut universe = Universe::default();
+        universe.set_pattern(-7, -2, &Pattern::from_rle("bo$2bo$3o!").unwrap());
+        assert_eq!(5, universe.population());
+        assert_eq!(Some(Periodicity::Periodic { start: 0, period: 4, dx: 1, dy: 1 }), universe.find_period(100));
+
+        let mut universe = Universe::default();
+        universe.set_pattern(3, 3, &Pattern::from_rle("3o!").unwrap());
+        universe.step();
+        assert_eq!(Some(Periodicity::Periodic { start: 1, period: 2, dx: 0, dy: 0 }), universe.find_period(100));
+
+        let mut universe = Universe::default();
+        universe.set_pattern(0, 0, &Pattern::from_rle("2o!").unwrap());
+        assert_eq!(Some(Periodicity::DiesOut { generation: 1 }), universe.find_period(100));
+
+        let mut universe = Universe::default();
+        universe.set_pattern(0, 0, &Pattern::from_rle("b2o$2o$bo!").unwrap());
+        assert_eq!(None, universe.find_period(10));
+    }
+
+    #[test]
+    fn test_universe_from_file() {
+        let universe = Universe::from_file("replicator.rle", b"x = 3, y = 1, rule = 23/36\n3o!").unwrap();
+        assert_eq!("B36/S23", universe.rule());
+        assert_eq!(3, universe.population());
+
+        let universe = Universe::from_file("blinker.cells", b"OOO").unwrap();
+        assert_eq!("B3/S23", universe.rule());
+        assert_eq!(Some((0, 0, 3, 1)), universe.bounding_box());
+
+        let snapshot = Universe::from_file("blinker.cells", b"OOO").unwrap().save();
+        assert_eq!(3, Universe::from_file("blinker.conk", &snapshot).unwrap().population());
+        assert!(Universe::from_file("blinker.txt", b"OOO").is_err());
+    }
+
+    #[test]
+    fn test_universe_open() {
+        let path = std::env::temp_dir().join(format!("conk-open-{}.cells", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "OOO").unwrap();
+        assert_eq!("B3/S23", Universe::open(path, None).unwrap().rule());
+        assert_eq!("B36/S23", Universe::open(path, Some("B36/S23")).unwrap().rule());
+        assert!(Universe::open(path, Some("B9")).is_err());
+        std::fs::remove_file(path).unwrap();
+        assert!(Universe::open(path, None).is_err());
+    }
+
+    #[test]
+    fn test_gc_policy() {
+        let glider = Pattern::from_rle("bo$2bo$3o!").unwrap();
+        let run = |policy: GcPolicy| {
             let mut universe = Universe::default();
-            universe.set_cell(0, 0);
-            universe.set_cell(1, 0);
-            universe.set_cell(2, 0);
-            universe.set_cell(2, -1);
-            universe.set_cell(1, -2);
-            
-            for _i in 0..1000 {
+            universe.set_gc_policy(policy);
+            // A row of gliders, each crossing a few 512x512 cell chunks.
+            for index in 0..20 {
+                universe.set_pattern(index * 1024, 0, &glider);
+            }
+            for _ in 0..4000 {
                 universe.step();
             }
+            let stats = universe.memory_stats();
+            assert!(stats.tiles <= 20 * 8);
+            assert!(stats.active_tiles < 20 * 20);
+            universe
+        };
+
+        let never = run(GcPolicy::Never);
+        let stats = never.memory_stats();
+        assert_eq!(0, stats.collections);
+        assert!(stats.chunks > 60);
+        assert!(stats.empty_chunks > 50);
+
+        let ratio = run(GcPolicy::ZeroRatio(0.5));
+        let stats = ratio.memory_stats();
+        assert!(stats.collections > 0);
+        assert!(stats.chunks * 2 < never.memory_stats().chunks);
+        assert_eq!(never.bounding_box(), ratio.bounding_box());
+
+        let interval = run(GcPolicy::Interval(500));
+        let stats = interval.memory_stats();
+        assert_eq!(8, stats.collections);
+        assert_eq!(0, stats.empty_chunks);
+        assert_eq!(never.bounding_box(), interval.bounding_box());
+    }
 
-            let expected = 5;
-            assert_eq!(expected, universe.p01.values().map(|v| v.0.count_ones()).sum::<u32>());
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_threads_agree() {
+        // Soups in many chunks, so threads get work and share edges.
+        let mut soups = Universe::default();
+        for chunk_y in -2..2 {
+            for chunk_x in -2..2 {
+                for (x, y) in soup((23 + chunk_y * 4 + chunk_x) as u64, chunk_x * 512 + 480, chunk_y * 512 + 480, 64) {
+                    soups.set_cell(x, y);
+                }
+            }
         }
 
-        extern crate test;    
-        use test::{Bencher, black_box};
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let mut universe = soups.clone();
+            pool.install(|| {
+                for _ in 0..150 {
+                    universe.step();
+                }
+            });
+            universe
+        };
+        let single = run(1);
+        let many = run(8);
+        assert!(single.population() > 0);
+        assert_eq!(single.save(), many.save());
+        assert_eq!(single.memory_stats(), many.memory_stats());
+    }
 
-        #[bench]
-        fn bench_p01_calc(b: &mut Bencher) {
-            let universe = Universe::default();
-            let tile = Tile(0xc800_2220);
-            let right = Tile(0x8880_0008);
-            let down = Tile(0xd200_0000);
-            let downright = Tile(0x8000_0000);
+    #[test]
+    fn test_pack_tiles() {
+        let mut universe = Universe::default();
+        for (x, y) in soup(0x2545_f491_4f6c_dd1d, -30, -30, 60) {
+            universe.set_cell(x, y);
+        }
+        for _ in 0..2 {
+            universe.step();
+            let view = universe.visible_tiles(-24, -16, 48, 32);
+            let (x, y, width, height) = (-4, -3, 8, 6);
+            let bytes = view.pack(x, y, width, height);
+            assert_eq!((width * height * 8) as usize, bytes.len());
+
+            let mut packed = Vec::new();
+            for (index, byte) in bytes.iter().enumerate() {
+                let index = index as i64;
+                let (tile, row) = (index / 8, index % 8);
+                for column in 0..8 {
+                    if byte & (0x80 >> column) != 0 {
+                        packed.push((
+                            (x + tile % width) * 8 + column + view.offset,
+                            (y + tile / width) * 8 + row + view.offset));
+                    }
+                }
+            }
+            packed.sort_unstable();
+            let mut expected: Vec<(i64, i64)> = view.cells().into_iter()
+                .filter(|&(cell_x, cell_y)| {
+                    (x * 8..(x + width) * 8).contains(&(cell_x - view.offset))
+                        && (y * 8..(y + height) * 8).contains(&(cell_y - view.offset))
+                })
+                .collect();
+            expected.sort_unstable();
+            assert!(!expected.is_empty());
+            assert_eq!(expected, packed);
+        }
+    }
 
-            b.iter(|| {
-                // Inner closure, the actual test
-                for _i in 1..100 {
-                    black_box(universe.p01_calc(tile, right, down, downright));
+    #[test]
+    fn test_density() {
+        let mut universe = Universe::default();
+        for y in 0..16 {
+            for x in 0..16 {
+                if (x + y) % 4 == 0 {
+                    universe.set_cell(x, y);
                 }
-            });
+            }
+        }
+        universe.set_cell(-100, -100);
+        for _ in 0..2 {
+            let view = universe.visible_tiles(-128, -128, 256, 256);
+            for &block in &[2, 4, 8, 16, 64] {
+                let density = view.density(-128, -128, 256, 256, block);
+                assert_eq!((density.width * density.height) as usize, density.levels.len());
+
+                let mut counts = vec![0; density.levels.len()];
+                for (x, y) in view.cells() {
+                    let grid_x = (x - view.offset).div_euclid(block) - density.x;
+                    let grid_y = (y - view.offset).div_euclid(block) - density.y;
+                    counts[(grid_y * density.width + grid_x) as usize] += 1;
+                }
+                let expected: Vec<u8> = counts.into_iter()
+                    .map(|count| if count == 0 { 0 } else { (count * 255 / (block * block)).max(1) as u8 })
+                    .collect();
+                assert_eq!(expected, density.levels, "blocks of {}", block);
+            }
+            universe.step();
+        }
+
+        // Huge blocks still show the few cells in them.
+        let density = universe.visible_tiles(-1 << 40, -1 << 40, 1 << 41, 1 << 41)
+            .density(-1 << 40, -1 << 40, 1 << 41, 1 << 41, 1 << 41);
+        assert_eq!(4, density.levels.len());
+        assert!(density.levels.iter().all(|&level| level <= 1));
+        assert!(density.levels.contains(&1));
+    }
+
+    #[test]
+    fn test_visible_tiles() {
+        let mut universe = Universe::default();
+        let cells = [(-600, -600), (-1, -1), (0, 0), (7, 7), (8, 8), (100, 3), (2000, 2000)];
+        for &(x, y) in &cells {
+            universe.set_cell(x, y);
+        }
+        for generations in 0..2 {
+            let view = universe.visible_tiles(-4, -4, 16, 12);
+            let mut visible: Vec<(i64, i64)> = view.cells().into_iter()
+                .filter(|&(x, y)| (-4..12).contains(&x) && (-4..8).contains(&y))
+                .collect();
+            visible.sort_unstable();
+            let mut expected: Vec<(i64, i64)> = universe.live_cells().into_iter()
+                .filter(|&(x, y)| (-4..12).contains(&x) && (-4..8).contains(&y))
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(expected, visible, "after {} generations", generations);
+            assert!(view.tiles.len() <= 6);
+
+            // A view wider than every chunk put together finds the same tiles.
+            let all = universe.visible_tiles(-1 << 40, -1 << 40, 1 << 41, 1 << 41);
+            let mut all = all.cells();
+            all.sort_unstable();
+            let mut live = universe.live_cells();
+            live.sort_unstable();
+            assert_eq!(live, all);
+
+            for &(x, y) in &cells {
+                universe.set_cell(x, y + 1);
+            }
+            universe.step();
         }
     }
-}   */
\ No newline at end of file
+}
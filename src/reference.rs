@@ -0,0 +1,204 @@
+// A deliberately simple engine that keeps live cells in a hash set and
+// counts neighbours cell by cell. It is far too slow for the app, but easy
+// to trust, so the tiled engine in life is tested against it.
+
+use fnv::{FnvHashMap, FnvHashSet};
+
+#[derive(Clone, Debug, Default)]
+pub struct NaiveUniverse {
+    cells: FnvHashSet<(i64, i64)>,
+    birth: Vec<u32>,
+    survival: Vec<u32>,
+    pub generation: u64,
+}
+
+impl NaiveUniverse {
+    // Rules with B0 would fill the infinite plane, so like Universe this
+    // only supports rules without it.
+    pub fn new(birth: Vec<u32>, survival: Vec<u32>) -> NaiveUniverse {
+        NaiveUniverse { birth, survival, ..NaiveUniverse::default() }
+    }
+
+    pub fn set_cell(&mut self, x: i64, y: i64) {
+        self.cells.insert((x, y));
+    }
+
+    pub fn kill_cell(&mut self, x: i64, y: i64) {
+        self.cells.remove(&(x, y));
+    }
+
+    pub fn step(&mut self) {
+        let mut neighbours: FnvHashMap<(i64, i64), u32> = FnvHashMap::default();
+        for &(x, y) in &self.cells {
+            // Live cells with no neighbours still need a count for S0.
+            neighbours.entry((x, y)).or_insert(0);
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if dx != 0 || dy != 0 {
+                        *neighbours.entry((x.wrapping_add(dx), y.wrapping_add(dy))).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        self.cells = neighbours.into_iter()
+            .filter(|(cell, count)| {
+                if self.cells.contains(cell) {
+                    self.survival.contains(count)
+                } else {
+                    self.birth.contains(count)
+                }
+            })
+            .map(|(cell, _)| cell)
+            .collect();
+        self.generation += 1;
+    }
+
+    // Sorted by row, then column, to compare with other engines.
+    pub fn live_cells(&self) -> Vec<(i64, i64)> {
+        let mut cells: Vec<(i64, i64)> = self.cells.iter().cloned().collect();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells
+    }
+}
+
+// A xorshift generator, so soups are random but repeatable. The seed must
+// not be zero.
+pub struct Random(pub u64);
+
+impl Random {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+// A size x size soup at (x, y), with each cell alive one time in three, for
+// tests and benchmarks.
+pub fn soup(seed: u64, x: i64, y: i64, size: i64) -> Vec<(i64, i64)> {
+    let mut random = Random(seed);
+    let mut cells = Vec::new();
+    for cell_y in 0..size {
+        for cell_x in 0..size {
+            if random.next_u64().is_multiple_of(3) {
+                cells.push((x + cell_x, y + cell_y));
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catalogue::RuleSet;
+    use crate::life::{parse_rule, Universe};
+
+    // Places the cells in both engines, first stepping to an odd generation
+    // if asked, and checks they agree for the given number of generations.
+    fn compare(rule: &str, cells: &[(i64, i64)], odd: bool, generations: u64) {
+        let (birth, survival) = parse_rule(rule).unwrap();
+        let mut universe = Universe::new(birth.clone(), survival.clone());
+        let mut naive = NaiveUniverse::new(birth, survival);
+        if odd {
+            universe.step();
+            naive.step();
+        }
+        for &(x, y) in cells {
+            universe.set_cell(x, y);
+            naive.set_cell(x, y);
+        }
+
+        for _ in 0..=generations {
+            let mut cells = universe.live_cells();
+            cells.sort_by_key(|&(x, y)| (y, x));
+            assert_eq!(naive.live_cells(), cells, "{} differs at generation {}", rule, naive.generation);
+            assert_eq!(naive.generation, universe.generation);
+            universe.step();
+            naive.step();
+        }
+    }
+
+    #[test]
+    fn test_naive_blinker() {
+        let mut naive = NaiveUniverse::new(vec!(3), vec!(2, 3));
+        naive.set_cell(-1, 0);
+        naive.set_cell(0, 0);
+        naive.set_cell(1, 0);
+        naive.step();
+        assert_eq!(vec!((0, -1), (0, 0), (0, 1)), naive.live_cells());
+        naive.kill_cell(0, 0);
+        naive.step();
+        assert!(naive.live_cells().is_empty());
+    }
+
+    #[test]
+    fn test_random_soups() {
+        for seed in 1..6 {
+            compare("B3/S23", &soup(seed, 0, 0, 24), false, 200);
+        }
+    }
+
+    #[test]
+    fn test_rulesets() {
+        for ruleset in RuleSet::all() {
+            for &odd in &[false, true] {
+                compare(ruleset.rule(), &soup(7, -5, -5, 16), odd, 60);
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_coordinates() {
+        for &odd in &[false, true] {
+            compare("B3/S23", &soup(11, -13, -9, 20), odd, 200);
+            compare("B36/S23", &soup(12, -41, -33, 20), odd, 200);
+        }
+    }
+
+    #[test]
+    fn test_far_coordinates() {
+        let far = 1 << 32;
+        for &odd in &[false, true] {
+            for &(x, y) in &[(far - 10, far - 7), (-far - 10, -far - 7), (far - 9, -far - 6), (-far - 9, far - 6)] {
+                compare("B3/S23", &soup(13, x, y, 20), odd, 150);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edits_while_running() {
+        let mut universe = Universe::default();
+        let mut naive = NaiveUniverse::new(vec!(3), vec!(2, 3));
+        for &(x, y) in &soup(17, -10, -10, 20) {
+            universe.set_cell(x, y);
+            naive.set_cell(x, y);
+        }
+
+        let mut random = Random(19);
+        for generation in 0..120 {
+            // Edits land on both parities, and the rule changes halfway.
+            let x = (random.next_u64() % 24) as i64 - 12;
+            let y = (random.next_u64() % 24) as i64 - 12;
+            if random.next_u64().is_multiple_of(2) {
+                universe.set_cell(x, y);
+                naive.set_cell(x, y);
+            } else {
+                universe.kill_cell(x, y);
+                naive.kill_cell(x, y);
+            }
+            if generation == 61 {
+                universe.set_rules(vec!(3, 6), vec!(2, 3));
+                naive.birth = vec!(3, 6);
+            }
+
+            let mut cells = universe.live_cells();
+            cells.sort_by_key(|&(x, y)| (y, x));
+            assert_eq!(naive.live_cells(), cells, "differs at generation {}", generation);
+            universe.step();
+            naive.step();
+        }
+    }
+}
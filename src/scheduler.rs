@@ -0,0 +1,147 @@
+// Decides how many generations to run each animation frame. A target rate is
+// spread over the frames, and a time budget caps each frame's share, so a
+// heavy pattern slows the simulation rather than the page. The time per
+// generation is learned from the runs reported back.
+
+use std::collections::VecDeque;
+
+// The achieved rate is averaged over this much time.
+const RATE_WINDOW_MS: f64 = 1000.0;
+// How quickly the time per generation follows new measurements.
+const SMOOTHING: f64 = 0.25;
+const INITIAL_MS_PER_GENERATION: f64 = 0.1;
+
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    // Generations per second, or None for as many as the budget allows.
+    target: Option<f64>,
+    budget_ms: f64,
+    ms_per_generation: f64,
+    // Generations due at the target rate and not yet run.
+    owed: f64,
+    last_plan_ms: Option<f64>,
+    // When recent runs finished, and how many generations each ran.
+    runs: VecDeque<(f64, u64)>,
+}
+
+impl Scheduler {
+
+    pub fn new(target: Option<f64>, budget_ms: f64) -> Scheduler {
+        Scheduler {
+            target,
+            budget_ms,
+            ms_per_generation: INITIAL_MS_PER_GENERATION,
+            owed: 0.0,
+            last_plan_ms: None,
+            runs: VecDeque::new(),
+        }
+    }
+
+    pub fn set_target(&mut self, target: Option<f64>) {
+        self.target = target;
+        self.owed = 0.0;
+    }
+
+    // Forgets time spent paused, so playing again doesn't start with a burst.
+    pub fn pause(&mut self) {
+        self.last_plan_ms = None;
+        self.owed = 0.0;
+    }
+
+    // The generations to run for the frame at now_ms, possibly none.
+    pub fn plan(&mut self, now_ms: f64) -> u64 {
+        let elapsed_ms = self.last_plan_ms.map_or(0.0, |last| (now_ms - last).max(0.0));
+        self.last_plan_ms = Some(now_ms);
+
+        let affordable = (self.budget_ms / self.ms_per_generation).floor().max(1.0);
+        match self.target {
+            Some(rate) => {
+                // Generations the budget can't fit are dropped, not saved up.
+                self.owed = (self.owed + rate * elapsed_ms / 1000.0).min(affordable);
+                self.owed.floor() as u64
+            },
+            None => affordable as u64,
+        }
+    }
+
+    // Reports that a run of generations took elapsed_ms, finishing at now_ms.
+    pub fn ran(&mut self, now_ms: f64, generations: u64, elapsed_ms: f64) {
+        if generations == 0 {
+            return;
+        }
+        self.owed = (self.owed - generations as f64).max(0.0);
+        let sample = (elapsed_ms / generations as f64).max(f64::EPSILON);
+        self.ms_per_generation += SMOOTHING * (sample - self.ms_per_generation);
+
+        self.runs.push_back((now_ms, generations));
+        while self.runs.front().is_some_and(|&(finished, _)| finished <= now_ms - RATE_WINDOW_MS) {
+            self.runs.pop_front();
+        }
+    }
+
+    // Generations per second over the last RATE_WINDOW_MS.
+    pub fn rate(&self, now_ms: f64) -> f64 {
+        let generations: u64 = self.runs.iter()
+            .filter(|&&(finished, _)| finished > now_ms - RATE_WINDOW_MS)
+            .map(|&(_, generations)| generations)
+            .sum();
+        generations as f64 * 1000.0 / RATE_WINDOW_MS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Plans and runs frames 1000/60 ms apart, each generation taking
+    // ms_per_generation, and returns the generations run.
+    fn simulate(scheduler: &mut Scheduler, frames: u64, ms_per_generation: f64) -> u64 {
+        let mut total = 0;
+        for frame in 0..frames {
+            let now = frame as f64 * 1000.0 / 60.0;
+            let generations = scheduler.plan(now);
+            scheduler.ran(now, generations, generations as f64 * ms_per_generation);
+            total += generations;
+        }
+        total
+    }
+
+    #[test]
+    fn test_target_rate() {
+        let mut scheduler = Scheduler::new(Some(1000.0), 10.0);
+        let total = simulate(&mut scheduler, 600, 0.001);
+        assert!((9900..=10000).contains(&total), "{}", total);
+        assert!((scheduler.rate(10_000.0) - 1000.0).abs() < 20.0);
+
+        let mut scheduler = Scheduler::new(Some(1.0), 10.0);
+        let total = simulate(&mut scheduler, 601, 0.001);
+        assert!((9..=10).contains(&total), "{}", total);
+    }
+
+    #[test]
+    fn test_budget() {
+        // Generations taking 1ms fit ten to a 10ms frame, whatever the target.
+        let mut scheduler = Scheduler::new(Some(1000.0), 10.0);
+        simulate(&mut scheduler, 60, 1.0);
+        assert_eq!(10, scheduler.plan(1000.0 + 1000.0 / 60.0));
+
+        // The first frames overshoot while the time per generation is learned.
+        let mut scheduler = Scheduler::new(None, 10.0);
+        let total = simulate(&mut scheduler, 600, 1.0);
+        assert!((5900..=6200).contains(&total), "{}", total);
+
+        // Even generations longer than the budget run one a frame.
+        let mut scheduler = Scheduler::new(None, 10.0);
+        simulate(&mut scheduler, 60, 50.0);
+        assert_eq!(1, scheduler.plan(1000.0 + 1000.0 / 60.0));
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut scheduler = Scheduler::new(Some(60.0), 10.0);
+        simulate(&mut scheduler, 10, 0.001);
+        scheduler.pause();
+        assert_eq!(0, scheduler.plan(60_000.0));
+        assert_eq!(1, scheduler.plan(60_000.0 + 1000.0 / 60.0 + 0.1));
+    }
+}
@@ -0,0 +1,188 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::io::{Read, Write};
+
+// Links longer than this are truncated or refused by too many browsers,
+// chat clients and URL shorteners to be worth sharing.
+pub const MAX_FRAGMENT_LENGTH: usize = 8000;
+
+// Version 1 links held the RLE as it is; version 2 deflates it and
+// encodes it as URL-safe base64. Both are read.
+const VERSION: &str = "2";
+const PLAIN_VERSION: &str = "1";
+
+// What a shared link opens: the live cells as an RLE body (without its
+// header line) placed at (cells_x, cells_y), the rule, the generation, the
+// camera and whether the simulation is running.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedView {
+    pub cells: String,
+    pub cells_x: i64,
+    pub cells_y: i64,
+    pub rule: String,
+    pub generation: u64,
+    pub x: f32,
+    pub y: f32,
+    pub cell_size: f32,
+    pub playing: bool,
+}
+
+impl SharedView {
+    // The URL fragment, without the leading '#', or None when it would be
+    // longer than MAX_FRAGMENT_LENGTH. The pattern goes last so a truncated
+    // link fails to parse rather than silently losing cells.
+    pub fn to_fragment(&self) -> Option<String> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(self.cells.as_bytes()).ok()?;
+        let packed = URL_SAFE_NO_PAD.encode(encoder.finish().ok()?);
+
+        let fragment = format!(
+            "v={}&rule={}&gen={}&x={}&y={}&size={}&play={}&at={},{}&p={}",
+            VERSION, self.rule, self.generation, self.x, self.y, self.cell_size,
+            if self.playing { 1 } else { 0 }, self.cells_x, self.cells_y, packed,
+        );
+
+        if fragment.len() > MAX_FRAGMENT_LENGTH {
+            None
+        } else {
+            Some(fragment)
+        }
+    }
+
+    // Parses a fragment made by to_fragment, with or without the leading '#'.
+    // It should already be percent-decoded.
+    pub fn from_fragment(fragment: &str) -> Result<SharedView, String> {
+        let mut version = None;
+        let mut rule = None;
+        let mut generation = None;
+        let mut x = None;
+        let mut y = None;
+        let mut cell_size = None;
+        let mut playing = None;
+        let mut at = None;
+        let mut cells = None;
+
+        for field in fragment.trim_start_matches('#').split('&') {
+            let mut parts = field.splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().ok_or_else(|| format!("\"{}\" has no value", key))?;
+            match key {
+                "v" => version = Some(value),
+                "rule" => rule = Some(value.to_string()),
+                "gen" => generation = Some(parse(key, value)?),
+                "x" => x = Some(parse(key, value)?),
+                "y" => y = Some(parse(key, value)?),
+                "size" => cell_size = Some(parse(key, value)?),
+                "play" => playing = Some(value == "1"),
+                "at" => {
+                    let mut coordinates = value.splitn(2, ',');
+                    let cells_x = parse(key, coordinates.next().unwrap_or(""))?;
+                    let cells_y = parse(key, coordinates.next().unwrap_or(""))?;
+                    at = Some((cells_x, cells_y));
+                },
+                "p" => cells = Some(value.to_string()),
+                _ => (),
+            }
+        }
+
+        let cells = cells.ok_or("the link has no pattern");
+        let cells = match version {
+            Some(VERSION) => unpack(&cells?)?,
+            Some(PLAIN_VERSION) => cells?,
+            Some(version) => return Err(format!("unsupported link version {}", version)),
+            None => return Err("not a shared link".to_string()),
+        };
+        if !cells.ends_with('!') {
+            return Err("the link was cut short".to_string());
+        }
+        let (cells_x, cells_y) = at.unwrap_or((0, 0));
+        let cell_size: f32 = cell_size.unwrap_or(10.0);
+        if !(cell_size.is_finite() && cell_size > 0.0) {
+            return Err(format!("\"{}\" is not a valid size", cell_size));
+        }
+
+        Ok(SharedView {
+            cells,
+            cells_x,
+            cells_y,
+            rule: rule.ok_or("the link has no rule")?,
+            generation: generation.unwrap_or(0),
+            x: x.unwrap_or(0.0),
+            y: y.unwrap_or(0.0),
+            cell_size,
+            playing: playing.unwrap_or(false),
+        })
+    }
+}
+
+fn unpack(packed: &str) -> Result<String, String> {
+    let deflated = URL_SAFE_NO_PAD.decode(packed).map_err(|_| "the pattern is not valid base64".to_string())?;
+    let mut cells = String::new();
+    DeflateDecoder::new(&deflated[..]).read_to_string(&mut cells)
+        .map_err(|_| "the link was cut short".to_string())?;
+    Ok(cells)
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("\"{}\" is not a valid {}", value, key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn glider() -> SharedView {
+        SharedView {
+            cells: "bo$2bo$3o!".to_string(),
+            cells_x: -5,
+            cells_y: 12,
+            rule: "B3/S23".to_string(),
+            generation: 41,
+            x: -120.5,
+            y: 33.0,
+            cell_size: 7.25,
+            playing: true,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let view = glider();
+        let fragment = view.to_fragment().unwrap();
+        assert_eq!(Ok(view.clone()), SharedView::from_fragment(&fragment));
+        assert_eq!(Ok(view), SharedView::from_fragment(&format!("#{}", fragment)));
+    }
+
+    #[test]
+    fn test_plain_link() {
+        let fragment = "v=1&rule=B3/S23&gen=41&x=-120.5&y=33&size=7.25&play=1&at=-5,12&p=bo$2bo$3o!";
+        assert_eq!(Ok(glider()), SharedView::from_fragment(fragment));
+    }
+
+    #[test]
+    fn test_too_large() {
+        // Repeats deflate well, so it takes runs of many lengths to make a
+        // link too long.
+        let mut view = glider();
+        view.cells = "o".repeat(MAX_FRAGMENT_LENGTH) + "!";
+        assert!(view.to_fragment().is_some());
+        view.cells = (1..5000).map(|run| format!("{}o{}b", run, run)).collect::<String>() + "!";
+        assert_eq!(None, view.to_fragment());
+    }
+
+    #[test]
+    fn test_bad_fragments() {
+        let fragment = glider().to_fragment().unwrap();
+        assert!(SharedView::from_fragment("").is_err());
+        assert!(SharedView::from_fragment(&fragment.replace("v=2", "v=9")).is_err());
+        assert!(SharedView::from_fragment(&fragment.replace("gen=41", "gen=many")).is_err());
+        assert!(SharedView::from_fragment(&fragment[..fragment.len() - 4]).is_err());
+        assert!(SharedView::from_fragment(&fragment.replace("size=7.25", "size=0")).is_err());
+        assert!(SharedView::from_fragment(&fragment.replace("size=7.25", "size=-3")).is_err());
+        assert!(SharedView::from_fragment(&fragment.replace("size=7.25", "size=NaN")).is_err());
+        assert!(SharedView::from_fragment(&fragment.replace("size=7.25", "size=inf")).is_err());
+    }
+}
@@ -0,0 +1,35 @@
+#version 300 es
+
+precision highp float;
+precision highp int;
+precision highp usampler2D;
+
+// The visible tiles, two texels each: the four bytes of a texel are four
+// rows of eight cells, top row first, with the leftmost cell in the high
+// bit. This is how life::TileView::pack lays them out.
+uniform usampler2D u_tiles;
+// Where the top left cell of the texture is on the canvas, in pixels.
+uniform vec2 u_origin;
+uniform float u_cell_size;
+uniform float u_height;
+uniform vec4 u_color;
+
+out vec4 color;
+
+void main() {
+   vec2 pixel = vec2(gl_FragCoord.x, u_height - gl_FragCoord.y);
+   ivec2 cell = ivec2(floor((pixel - u_origin) / u_cell_size));
+   ivec2 tile = cell / 8;
+   ivec2 size = textureSize(u_tiles, 0);
+   if (cell.x < 0 || cell.y < 0 || tile.x * 2 >= size.x || tile.y >= size.y) {
+      discard;
+   }
+
+   int row = cell.y % 8;
+   uvec4 rows = texelFetch(u_tiles, ivec2(tile.x * 2 + row / 4, tile.y), 0);
+   uint bits = rows[row % 4];
+   if (((bits >> uint(7 - cell.x % 8)) & 1u) == 0u) {
+      discard;
+   }
+   color = u_color;
+}
@@ -0,0 +1,12 @@
+#version 300 es
+
+in vec2 a_position;
+
+uniform vec2 u_resolution;
+
+void main() {
+   // the same conversion from pixels to clipspace as life.vert
+   vec2 clipSpace = a_position / u_resolution * 2.0 - 1.0;
+
+   gl_Position = vec4(clipSpace * vec2(1, -1), 0, 1);
+}
@@ -0,0 +1,409 @@
+// The engine runs in a Web Worker, so stepping a heavy pattern never holds
+// up panning, zooming or drawing. The page keeps its own copy of the
+// universe for edits, undo and exports: every edit is applied to both, the
+// worker answers each request with the tiles in view, and it sends its
+// whole universe when the page's copy has fallen behind.
+
+use crate::life::{DensityView, MemoryStats, Pattern, PasteMode, TileView, Universe};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+use yew::Callback;
+
+// Loads the engine and passes on requests, see static/worker.js.
+#[cfg(not(feature = "threads"))]
+const WORKER_SCRIPT: &str = "worker.js";
+// The thread pool is started from a module worker, see
+// static/worker-threads.js.
+#[cfg(feature = "threads")]
+const WORKER_SCRIPT: &str = "worker-threads.js";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Edit {
+    SetCell(i64, i64),
+    KillCell(i64, i64),
+    ToggleCell(i64, i64),
+    SetPattern(i64, i64, Pattern),
+    PastePattern(i64, i64, Pattern, PasteMode),
+    ClearRegion(i64, i64, i64, i64),
+    Clear,
+    SetRules(Vec<u32>, Vec<u32>),
+}
+
+impl Edit {
+
+    pub fn apply(&self, universe: &mut Universe) {
+        match self {
+            Edit::SetCell(x, y) => universe.set_cell(*x, *y),
+            Edit::KillCell(x, y) => universe.kill_cell(*x, *y),
+            Edit::ToggleCell(x, y) => universe.toggle_cell(*x, *y),
+            Edit::SetPattern(x, y, pattern) => universe.set_pattern(*x, *y, pattern),
+            Edit::PastePattern(x, y, pattern, mode) => universe.paste_pattern(*x, *y, pattern, *mode),
+            Edit::ClearRegion(x, y, width, height) => universe.clear_region(*x, *y, *width, *height),
+            Edit::Clear => universe.clear(),
+            Edit::SetRules(birth, survival) => universe.set_rules(birth.clone(), survival.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    Edit(Vec<Edit>),
+    Run(u64),
+    // The cells on screen, as x, y, width and height, and the side of the
+    // blocks to draw them in: 1 for single cells.
+    View(i64, i64, i64, i64, i64),
+    // Replaces the universe with a snapshot.
+    Load(Vec<u8>),
+    // Asks for a snapshot of the universe.
+    Sync,
+}
+
+impl Request {
+
+    // Applies a request to a copy of the universe kept in step with the worker's.
+    pub fn apply(&self, universe: &mut Universe) {
+        match self {
+            Request::Edit(edits) => {
+                for edit in edits {
+                    edit.apply(universe);
+                }
+            },
+            Request::Run(generations) => {
+                for _ in 0..*generations {
+                    universe.step();
+                }
+            },
+            Request::Load(snapshot) => {
+                if let Ok(loaded) = Universe::load(snapshot) {
+                    *universe = loaded;
+                }
+            },
+            Request::View(..) | Request::Sync => (),
+        }
+    }
+}
+
+// What the page draws, and the numbers it shows beside it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub generation: u64,
+    pub population: u64,
+    pub memory: MemoryStats,
+    // The tiles in view, or when zoomed out to blocks, their density.
+    pub tiles: TileView,
+    pub density: Option<DensityView>,
+    // Generations run for the request this answers.
+    pub generations: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    Frame(Frame),
+    Snapshot(Vec<u8>),
+}
+
+// Tracks how far the page's copy of the universe is behind the worker's.
+// The page applies the requests it sends to its copy as well, except the
+// runs that play the simulation, after which its copy is stale until a
+// snapshot catches it up.
+#[derive(Debug, Default)]
+pub struct Mirror {
+    stale: bool,
+    // While a snapshot is on its way, the requests the page has applied
+    // since asking, to apply again on top of it, and whether the worker has
+    // stepped without the page in that time.
+    syncing: Option<Vec<Request>>,
+    ran_since_sync: bool,
+}
+
+impl Mirror {
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        self.syncing.is_some()
+    }
+
+    // Notes a request sent to the worker that the page has applied too.
+    pub fn mirrored(&mut self, request: &Request) {
+        if let Some(replay) = self.syncing.as_mut() {
+            replay.push(request.clone());
+        }
+    }
+
+    // Notes generations the worker runs without the page.
+    pub fn ran(&mut self) {
+        self.stale = true;
+        self.ran_since_sync = true;
+    }
+
+    // Notes that the worker was given the page's universe.
+    pub fn loaded(&mut self) {
+        self.stale = false;
+        self.ran_since_sync = false;
+    }
+
+    // Whether to ask the worker for a snapshot: the page has fallen behind
+    // and hasn't asked already.
+    pub fn start_sync(&mut self) -> bool {
+        if self.stale && self.syncing.is_none() {
+            self.syncing = Some(Vec::new());
+            self.ran_since_sync = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Catches the page's universe up with a snapshot from the worker.
+    pub fn synced(&mut self, universe: &mut Universe, snapshot: &[u8]) {
+        if let Ok(loaded) = Universe::load(snapshot) {
+            *universe = loaded;
+        }
+        for request in self.syncing.take().unwrap_or_default() {
+            request.apply(universe);
+        }
+        self.stale = self.ran_since_sync;
+    }
+}
+
+#[derive(Default)]
+pub struct Engine {
+    universe: Universe,
+    view: (i64, i64, i64, i64, i64),
+}
+
+impl Engine {
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        let mut generations = 0;
+        match request {
+            Request::Edit(edits) => {
+                for edit in edits {
+                    edit.apply(&mut self.universe);
+                }
+            },
+            Request::Run(count) => {
+                for _ in 0..count {
+                    self.universe.step();
+                }
+                generations = count;
+            },
+            Request::View(x, y, width, height, block) => self.view = (x, y, width, height, block),
+            Request::Load(snapshot) => {
+                if let Ok(universe) = Universe::load(&snapshot) {
+                    self.universe = universe;
+                }
+            },
+            Request::Sync => return Response::Snapshot(self.universe.save()),
+        }
+
+        let (x, y, width, height, block) = self.view;
+        let tiles = self.universe.visible_tiles(x, y, width, height);
+        let (tiles, density) = if block > 1 {
+            let density = tiles.density(x, y, width, height, block);
+            (TileView::default(), Some(density))
+        } else {
+            (tiles, None)
+        };
+        Response::Frame(Frame {
+            generation: self.universe.generation,
+            population: self.universe.population(),
+            memory: self.universe.memory_stats(),
+            tiles,
+            density,
+            generations,
+        })
+    }
+}
+
+// Answers requests from the page; called once the worker has loaded.
+pub fn listen() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let responder = scope.clone();
+    let mut engine = Engine::default();
+    let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let data = js_sys::Uint8Array::new(&event.data()).to_vec();
+        if let Ok(request) = bincode::deserialize(&data) {
+            let response = bincode::serialize(&engine.handle(request)).expect("responses always serialize");
+            let _ = responder.post_message(&js_sys::Uint8Array::from(&response[..]));
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+}
+
+#[cfg(not(feature = "threads"))]
+fn new_worker() -> Option<Worker> {
+    Worker::new(WORKER_SCRIPT).ok()
+}
+
+#[cfg(feature = "threads")]
+fn new_worker() -> Option<Worker> {
+    let options = web_sys::WorkerOptions::new();
+    options.set_type(web_sys::WorkerType::Module);
+    Worker::new_with_options(WORKER_SCRIPT, &options).ok()
+}
+
+// The page's end of the worker.
+pub struct Bridge {
+    worker: Worker,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl Bridge {
+
+    // None where workers are unavailable, such as pages opened from files.
+    pub fn new(callback: Callback<Response>) -> Option<Bridge> {
+        let worker = new_worker()?;
+        let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let data = js_sys::Uint8Array::new(&event.data()).to_vec();
+            if let Ok(response) = bincode::deserialize(&data) {
+                callback.emit(response);
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        Some(Bridge { worker, _onmessage: onmessage })
+    }
+
+    pub fn send(&self, request: &Request) {
+        let data = bincode::serialize(request).expect("requests always serialize");
+        let _ = self.worker.post_message(&js_sys::Uint8Array::from(&data[..]));
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.worker.terminate();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn as_frame(response: Response) -> Frame {
+        match response {
+            Response::Frame(frame) => frame,
+            Response::Snapshot(_) => panic!("expected a frame"),
+        }
+    }
+
+    #[test]
+    fn test_engine_follows_edits() {
+        let edits = vec![
+            Edit::SetPattern(0, 0, Pattern::from_rle("bo$2bo$3o!").unwrap()),
+            Edit::SetCell(100, 100),
+            Edit::ToggleCell(100, 100),
+            Edit::SetRules(vec!(3, 6), vec!(2, 3)),
+        ];
+        let mut engine = Engine::default();
+        let mut universe = Universe::default();
+        for edit in &edits {
+            edit.apply(&mut universe);
+        }
+
+        engine.handle(Request::View(-10, -10, 20, 20, 1));
+        let request: Request = bincode::deserialize(&bincode::serialize(&Request::Edit(edits)).unwrap()).unwrap();
+        assert_eq!(5, as_frame(engine.handle(request)).population);
+
+        let frame = as_frame(engine.handle(Request::Run(8)));
+        for _ in 0..8 {
+            universe.step();
+        }
+        assert_eq!(8, frame.generations);
+        assert_eq!(universe.generation, frame.generation);
+        let mut cells = frame.tiles.cells();
+        cells.sort_unstable();
+        let mut expected = universe.live_cells();
+        expected.sort_unstable();
+        assert_eq!(expected, cells);
+
+        match engine.handle(Request::Sync) {
+            Response::Snapshot(snapshot) => assert_eq!(universe.save(), snapshot),
+            Response::Frame(_) => panic!("expected a snapshot"),
+        }
+    }
+
+    fn as_snapshot(response: Response) -> Vec<u8> {
+        match response {
+            Response::Snapshot(snapshot) => snapshot,
+            Response::Frame(_) => panic!("expected a snapshot"),
+        }
+    }
+
+    fn sorted_cells(universe: &Universe) -> Vec<(i64, i64)> {
+        let mut cells = universe.live_cells();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn test_move_selection_while_running() {
+        // The page's universe and mirror, and the worker's engine.
+        let mut page = Universe::default();
+        let mut mirror = Mirror::default();
+        let mut engine = Engine::default();
+        page.set_pattern(0, 0, &Pattern::from_rle("3o!").unwrap());
+        engine.handle(Request::Load(page.save()));
+        mirror.loaded();
+
+        // The worker plays on without the page, so the selection's cells
+        // can't be read until a snapshot arrives.
+        engine.handle(Request::Run(3));
+        mirror.ran();
+        assert!(mirror.is_stale());
+        assert!(mirror.start_sync());
+        assert!(!mirror.start_sync());
+        let snapshot = as_snapshot(engine.handle(Request::Sync));
+
+        // An edit sent after asking is applied again on top of the snapshot.
+        let edit = Request::Edit(vec![Edit::SetCell(20, 20)]);
+        edit.apply(&mut page);
+        mirror.mirrored(&edit);
+        engine.handle(edit);
+        mirror.synced(&mut page, &snapshot);
+        assert!(!mirror.is_stale());
+        assert!(!mirror.is_syncing());
+
+        // Moving the blinker ten cells right, from cells read on the page,
+        // keeps the phase the worker had run it to.
+        let pattern = page.get_pattern(0, -1, 3, 3);
+        let edit = Request::Edit(vec![Edit::ClearRegion(0, -1, 3, 3), Edit::SetPattern(10, -1, pattern)]);
+        edit.apply(&mut page);
+        mirror.mirrored(&edit);
+        engine.handle(edit);
+
+        let worker = Universe::load(&as_snapshot(engine.handle(Request::Sync))).unwrap();
+        assert_eq!(vec!((11, -1), (11, 0), (11, 1), (20, 20)), sorted_cells(&worker));
+        assert_eq!(sorted_cells(&worker), sorted_cells(&page));
+        assert_eq!(worker.generation, page.generation);
+    }
+
+    #[test]
+    fn test_engine_frames_show_the_view() {
+        let mut engine = Engine::default();
+        engine.handle(Request::Edit(vec![Edit::SetCell(0, 0), Edit::SetCell(1000, 1000)]));
+        let frame = as_frame(engine.handle(Request::View(990, 990, 20, 20, 1)));
+        assert_eq!(2, frame.population);
+        assert_eq!(vec!((1000, 1000)), frame.tiles.cells());
+        assert_eq!(None, frame.density);
+
+        let frame = as_frame(engine.handle(Request::View(0, 0, 2048, 2048, 512)));
+        assert!(frame.tiles.tiles.is_empty());
+        let density = frame.density.unwrap();
+        assert_eq!((512, 16), (density.block, density.levels.len()));
+        assert_eq!(2, density.levels.iter().filter(|&&level| level > 0).count());
+
+        let mut universe = Universe::default();
+        universe.set_cell(5, 5);
+        engine.handle(Request::Load(universe.save()));
+        let frame = as_frame(engine.handle(Request::View(0, 0, 10, 10, 1)));
+        assert_eq!(vec!((5, 5)), frame.tiles.cells());
+    }
+}
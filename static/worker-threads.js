@@ -0,0 +1,16 @@
+// The engine's worker for builds with the `threads` feature. The crate is
+// built with `--target web` into worker/ instead, and this runs as a module
+// worker so wasm-bindgen-rayon can start the thread pool's workers from it.
+// Requests sent while it loads are kept until it is listening.
+import init, { initThreadPool, run_worker } from "./worker/conk.js";
+
+let queued = [];
+self.onmessage = event => queued.push(event);
+
+init()
+  .then(() => initThreadPool(navigator.hardwareConcurrency))
+  .then(() => {
+    run_worker();
+    queued.forEach(event => self.onmessage(event));
+    queued = [];
+  });
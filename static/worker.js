@@ -0,0 +1,13 @@
+// The engine's worker. The crate is built a second time with
+// `--target no-modules` into worker/, since workers can't load the bundle.
+// Requests sent while it loads are kept until it is listening.
+importScripts("worker/conk.js");
+
+let queued = [];
+self.onmessage = event => queued.push(event);
+
+wasm_bindgen("worker/conk_bg.wasm").then(() => {
+  wasm_bindgen.run_worker();
+  queued.forEach(event => self.onmessage(event));
+  queued = [];
+});
@@ -4,11 +4,24 @@ const CopyWebpackPlugin = require('copy-webpack-plugin');
 
 const distPath = path.resolve(__dirname, "dist");
 module.exports = (env, argv) => {
+  // `--env.threads` builds with the thread pool, see the README. Both builds
+  // need shared memory, which only nightly's std can be rebuilt with.
+  const threads = Boolean(env && env.threads);
+  const cargoArgs = threads ? " -- --features threads -Z build-std=panic_abort,std" : "";
+  if (threads) {
+    process.env.RUSTUP_TOOLCHAIN = "nightly";
+    process.env.RUSTFLAGS = "-C target-feature=+atomics,+bulk-memory";
+  }
   return {
     devServer: {
       contentBase: distPath,
       compress: argv.mode === 'production',
-      port: 8000
+      port: 8000,
+      // Shared memory is only given to cross-origin isolated pages.
+      headers: threads ? {
+        "Cross-Origin-Opener-Policy": "same-origin",
+        "Cross-Origin-Embedder-Policy": "require-corp"
+      } : {}
     },
     entry: './bootstrap.js',
     output: {
@@ -22,7 +35,14 @@ module.exports = (env, argv) => {
       ]),
       new WasmPackPlugin({
         crateDirectory: ".",
-        extraArgs: "--no-typescript",
+        extraArgs: "--no-typescript" + cargoArgs,
+      }),
+      // The same crate again for static/worker.js, which runs the engine,
+      // or for static/worker-threads.js, a module worker, with threads.
+      new WasmPackPlugin({
+        crateDirectory: ".",
+        outDir: path.resolve(distPath, "worker"),
+        extraArgs: "--no-typescript --target " + (threads ? "web" : "no-modules") + cargoArgs,
       })
     ],
     watch: argv.mode !== 'production'